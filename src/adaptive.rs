@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::cli::Args;
+use crate::quantity::Time;
+use crate::vectored::Vectored;
+
+//most the time step may grow by from one step to the next
+const MAX_GROWTH: f64 = 1.2;
+//safety factor on the step predicted from the energy error
+const SAFETY: f64 = 0.9;
+
+///Time step control limiting the largest atomic displacement in A
+///and the change of the conserved energy in kJ/mol over a step. The
+///step is kept within `min` and `max` fs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AdaptiveStep {
+    pub max_displacement: Option<f64>,
+    pub max_energy_error: Option<f64>,
+    pub min: f64,
+    pub max: f64,
+    //step allowed by the energy error of the last step or by a rollback, fs
+    #[serde(default)]
+    energy_step: Option<f64>,
+}
+
+impl AdaptiveStep {
+    ///None unless a displacement or energy limit is given. The upper
+    ///bound defaults to the initial time step in fs.
+    pub fn new(args: &Args, time_step: f64) -> Option<AdaptiveStep> {
+        if args.max_displacement.is_none() && args.max_energy_error.is_none() {
+            return None;
+        }
+        let unit = args.energy_unit;
+        Some(AdaptiveStep {
+            max_displacement: args.max_displacement,
+            max_energy_error: args.max_energy_error.map(|x| x / unit.convert(1.0)),
+            min: args.min_time_step,
+            max: args.max_time_step.unwrap_or(time_step),
+            energy_step: None,
+        })
+    }
+
+    ///time step for the next step from the current velocities and forces.
+    pub fn choose(&self, atoms: &[Atom]) -> Time {
+        let mut step = self.energy_step.unwrap_or(self.max).min(self.max);
+        if let Some(limit) = self.max_displacement {
+            for x in atoms.iter().filter(|x| x.can_mv) {
+                step = step.min(Self::displacement_step(x, limit));
+            }
+        }
+        Time::fs(step.max(self.min))
+    }
+
+    //largest step for which |v| dt + |a| dt^2 / 2 stays within the limit
+    fn displacement_step(atom: &Atom, limit: f64) -> f64 {
+        let speed = atom.vel.norm();
+        let acceleration = (atom.force / atom.mass).norm();
+        if acceleration == 0.0 {
+            return match speed == 0.0 {
+                true => f64::INFINITY,
+                false => limit / speed,
+            };
+        }
+        ((speed * speed + 2.0 * acceleration * limit).sqrt() - speed) / acceleration
+    }
+
+    ///cap the next step at `time_step`, as after a rollback of the watchdog.
+    pub fn limit(&mut self, time_step: Time) {
+        self.energy_step = Some(time_step.as_fs().clamp(self.min, self.max));
+    }
+
+    ///judge the energy error of a step of `time_step` and set the step
+    ///it allows. false when the step is to be redone with a shorter one.
+    pub fn accept(&mut self, error: f64, time_step: Time) -> bool {
+        let limit = match self.max_energy_error {
+            Some(x) => x,
+            None => {
+                //a cap left by a rollback is lifted gradually
+                self.energy_step = self
+                    .energy_step
+                    .map(|x| x * MAX_GROWTH)
+                    .filter(|x| *x < self.max);
+                return true;
+            }
+        };
+        let dt = time_step.as_fs();
+        //the energy error of a second order integrator goes as dt^2
+        let factor = match error > 0.0 {
+            true => (SAFETY * (limit / error).sqrt()).min(MAX_GROWTH),
+            false => MAX_GROWTH,
+        };
+        self.energy_step = Some((dt * factor).clamp(self.min, self.max));
+        error <= limit || dt <= self.min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::vectored::{Force, Velocity};
+
+    fn adaptive(max_energy_error: Option<f64>) -> AdaptiveStep {
+        AdaptiveStep {
+            max_displacement: None,
+            max_energy_error,
+            min: 0.1,
+            max: 2.0,
+            energy_step: None,
+        }
+    }
+
+    //time steps tried by the run loop on atoms at rest, with the energy
+    //error of a step of dt given by `error`, and whether each was kept
+    fn run(
+        adaptive: &mut AdaptiveStep,
+        error: impl Fn(f64) -> f64,
+        steps: usize,
+    ) -> Vec<(f64, bool)> {
+        let atoms = test_atoms(&[("C", [0.0; 3])]);
+        (0..steps)
+            .map(|_| {
+                let time_step = adaptive.choose(&atoms);
+                let dt = time_step.as_fs();
+                (dt, adaptive.accept(error(dt), time_step))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn energy_error_sequence() {
+        //an error of c dt^2 settles at 0.9 of the step meeting the limit
+        let mut step = adaptive(Some(0.01));
+        let tried = run(&mut step, |dt| 0.04 * dt * dt, 4);
+        let settled = SAFETY * (0.01 / 0.04_f64).sqrt();
+        assert_eq!(tried[0], (2.0, false));
+        for (dt, kept) in &tried[1..] {
+            assert!(*kept && (dt - settled).abs() < 1e-12, "{:?}", tried);
+        }
+
+        //a small error grows the step by at most MAX_GROWTH up to the maximum
+        step.energy_step = Some(0.5);
+        let tried = run(&mut step, |_| 1e-12, 10);
+        let expected = [
+            0.5, 0.6, 0.72, 0.864, 1.0368, 1.24416, 1.492992, 1.7915904, 2.0, 2.0,
+        ];
+        for ((dt, kept), expected) in tried.iter().zip(expected) {
+            assert!(*kept && (dt - expected).abs() < 1e-9, "{:?}", tried);
+        }
+
+        //a step failing at the minimum is kept rather than redone forever
+        let tried = run(&mut step, |_| 1.0, 4);
+        assert_eq!(
+            tried.iter().map(|x| x.1).collect::<Vec<_>>(),
+            [false, false, true, true]
+        );
+        assert_eq!(tried[3].0, step.min);
+    }
+
+    #[test]
+    fn rollback_limit() {
+        //without an energy limit the cap of a rollback is lifted by
+        //MAX_GROWTH per step
+        let mut step = adaptive(None);
+        step.limit(Time::fs(0.8));
+        let tried = run(&mut step, |_| 0.0, 7);
+        let expected = [0.8, 0.96, 1.152, 1.3824, 1.65888, 1.990656, 2.0];
+        for ((dt, kept), expected) in tried.iter().zip(expected) {
+            assert!(*kept && (dt - expected).abs() < 1e-9, "{:?}", tried);
+        }
+        assert_eq!(step.energy_step, None);
+
+        //with one, the step after the cap grows from it as well
+        let mut step = adaptive(Some(0.01));
+        step.limit(Time::fs(0.8));
+        let tried = run(&mut step, |_| 1e-12, 2);
+        assert!((tried[0].0 - 0.8).abs() < 1e-12 && (tried[1].0 - 0.96).abs() < 1e-12);
+
+        //the cap stays within the bounds
+        step.limit(Time::fs(0.01));
+        assert_eq!(step.energy_step, Some(step.min));
+        step.limit(Time::fs(5.0));
+        assert_eq!(step.energy_step, Some(step.max));
+    }
+
+    #[test]
+    fn displacement_limit() {
+        let mut step = adaptive(None);
+        step.max_displacement = Some(0.1);
+        let mut atoms = test_atoms(&[("C", [0.0; 3]), ("C", [1.5, 0.0, 0.0])]);
+        atoms[0].vel = Velocity::new(0.05, 0.0, 0.0);
+        assert!((step.choose(&atoms).as_fs() - 2.0).abs() < 1e-12);
+        atoms[1].vel = Velocity::new(0.0, 0.2, 0.0);
+        assert!((step.choose(&atoms).as_fs() - 0.5).abs() < 1e-12);
+        //|v| dt + |a| dt^2 / 2 = 0.1 at the chosen step
+        atoms[1].force = Force::new(0.0, 0.0, 12.011 * 0.3);
+        let dt = step.choose(&atoms).as_fs();
+        assert!((0.2 * dt + 0.5 * 0.3 * dt * dt - 0.1).abs() < 1e-12);
+        //frozen atoms do not count
+        atoms[1].can_mv = false;
+        assert!((step.choose(&atoms).as_fs() - 2.0).abs() < 1e-12);
+    }
+}
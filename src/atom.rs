@@ -1,7 +1,9 @@
 use std::fs::File;
 use std::io::Read;
 
-use crate::vectored::{Force, Position, Vectored, Velocity};
+use crate::quantity::Mass;
+use crate::units;
+use crate::vectored::{Force, Momentum, Position, Vectored, Velocity};
 use anyhow::{Context, Result};
 use rand_distr::{Distribution, Normal};
 use regex::Regex;
@@ -10,7 +12,7 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Atom {
     pub symbol: String,
-    pub mass: f64,
+    pub mass: Mass,
     pub can_mv: bool,
     pub pos: Position<f64>,
     pub vel: Velocity<f64>,
@@ -32,10 +34,9 @@ impl AtomFactory {
         let mut buffer = String::new();
         self.file.read_to_string(&mut buffer).unwrap();
         let data = AtomFactory::read_atomic_lines(buffer.clone())
-            .with_context(|| format!("Failed to read atomic data from input"))?;
+            .with_context(|| "Failed to read atomic data from input".to_string())?;
 
         let num_atoms = AtomFactory::get_num_atoms(buffer.clone());
-        println!("{}", num_atoms.clone());
         let atomic_lines = data
             .into_iter()
             .rev()
@@ -43,13 +44,9 @@ impl AtomFactory {
             .rev()
             .collect::<Vec<String>>();
 
-        for line in atomic_lines.clone() {
-            println!("{}", line);
-        }
-
         let atoms = atomic_lines
             .into_iter()
-            .map(|x| Self::make_atom(x))
+            .map(Self::make_atom)
             .collect::<Vec<Atom>>();
         let result = Self::rm_cmv(atoms);
 
@@ -95,26 +92,24 @@ impl AtomFactory {
             z.parse::<f64>().unwrap(),
         );
 
-        let velocity = Self::rand_vel(symbol_mass.mass);
+        let velocity = Self::rand_vel(symbol_mass.mass, 300.0);
         let vel = Velocity::new(velocity, velocity, velocity);
         let force = Force::new(0.0, 0.0, 0.0);
         let next_force = Force::new(0.0, 0.0, 0.0);
 
-        let result = Atom {
+        Atom {
             symbol: symbol_mass.symbol,
-            mass: symbol_mass.mass,
+            mass: Mass::amu(symbol_mass.mass),
             can_mv: true,
             pos,
             vel,
             force,
             next_force,
-        };
-
-        result
+        }
     }
 
     fn gn_symbol(num: u32) -> Result<SymbolMass> {
-        let result = match num {
+        match num {
             1 => Ok(SymbolMass::new("H", 1.008)),
             2 => Ok(SymbolMass::new("He", 4.0026)),
             6 => Ok(SymbolMass::new("C", 12.011)),
@@ -128,37 +123,25 @@ impl AtomFactory {
             47 => Ok(SymbolMass::new("Ag", 107.87)),
             79 => Ok(SymbolMass::new("Au", 196.97)),
             _ => Err(anyhow::anyhow!("atomic number: {}, is not supported!", num)),
-        };
-        result
+        }
     }
 
-    fn rand_vel(mass: f64) -> f64 {
-        //m^2*kg*s^-2*K^-1
-        let boltzmann = 1.380649e-23f64;
-        //K
-        let Temp = 300.0;
-        //kg
-        let new_mass = mass * (1.0 / 6.0221408e23f64) * (1.0 / 1000.0);
+    //temperature in K
+    fn rand_vel(mass: f64, Temp: f64) -> f64 {
         //A^2/fs^2
-        let var = ((boltzmann * Temp) / new_mass) * 10e-10f64;
+        let var = units::kt(Temp) / units::MD_ENERGY_KJ_MOL / mass;
         let normal = Normal::new(0.0, var.sqrt()).unwrap();
-        let value = normal.sample(&mut rand::thread_rng());
-        value
+        normal.sample(&mut rand::thread_rng())
     }
 
     fn rm_cmv(atoms: Vec<Atom>) -> Vec<Atom> {
-        let cmv_mv: Velocity<f64> = atoms
-            .clone()
-            .into_iter()
-            .map(|x| x.mass * x.vel)
-            .fold(Velocity::new(0.0, 0.0, 0.0), |a, b| a + b);
-        let cmv_m: f64 = atoms.clone().into_iter().map(|x| x.mass).sum();
-        let cmv = cmv_mv * (1.0 / cmv_m);
-        let result = atoms
+        let cmv_mv: Momentum<f64> = atoms.iter().map(|x| x.vel * x.mass).sum();
+        let cmv_m: Mass = atoms.iter().map(|x| x.mass).sum();
+        let cmv = cmv_mv / cmv_m;
+        atoms
             .into_iter()
             .map(|x| Self::apply_to_atom(x, cmv))
-            .collect::<Vec<Atom>>();
-        result
+            .collect::<Vec<Atom>>()
     }
 
     fn apply_to_atom(mut atom: Atom, value: Velocity<f64>) -> Atom {
@@ -167,6 +150,60 @@ impl AtomFactory {
     }
 }
 
+///Draw Maxwell-Boltzmann velocities at `temperature` for the mobile atoms.
+///The centre of mass velocity is removed unless atoms are frozen.
+pub fn draw_velocities(atoms: &mut Vec<Atom>, temperature: f64) {
+    for atom in atoms.iter_mut() {
+        atom.vel = match atom.can_mv {
+            true => Velocity::new(
+                AtomFactory::rand_vel(atom.mass.as_amu(), temperature),
+                AtomFactory::rand_vel(atom.mass.as_amu(), temperature),
+                AtomFactory::rand_vel(atom.mass.as_amu(), temperature),
+            ),
+            false => Velocity::new(0.0, 0.0, 0.0),
+        };
+    }
+    if atoms.iter().all(|x| x.can_mv) {
+        *atoms = AtomFactory::rm_cmv(atoms.clone());
+    }
+}
+
+///Single bond covalent radius in Angstrom (Pyykko and Atsumi, 2009).
+pub fn covalent_radius(symbol: &str) -> f64 {
+    match symbol {
+        "H" => 0.32,
+        "He" => 0.46,
+        "C" => 0.75,
+        "N" => 0.71,
+        "O" => 0.63,
+        "F" => 0.64,
+        "Ne" => 0.67,
+        "P" => 1.11,
+        "S" => 1.03,
+        "Cl" => 0.99,
+        "Ag" => 1.28,
+        "Au" => 1.24,
+        _ => 1.5,
+    }
+}
+
+///Perceive bonds from interatomic distances. Two atoms are bonded when
+///closer than the sum of their covalent radii plus a 0.4 Angstrom tolerance.
+///Pairs are zero based and ordered `(i, j)` with `i < j`.
+pub fn perceive_bonds(atoms: &[Atom]) -> Vec<(usize, usize)> {
+    let mut bonds = vec![];
+    for i in 0..atoms.len() {
+        for j in (i + 1)..atoms.len() {
+            let cutoff =
+                covalent_radius(&atoms[i].symbol) + covalent_radius(&atoms[j].symbol) + 0.4;
+            if (atoms[i].pos - atoms[j].pos).norm() < cutoff {
+                bonds.push((i, j));
+            }
+        }
+    }
+    bonds
+}
+
 struct SymbolMass {
     symbol: String,
     mass: f64,
@@ -180,3 +217,20 @@ impl SymbolMass {
         }
     }
 }
+
+///Atoms at rest at the given positions in A, for tests.
+#[cfg(test)]
+pub fn test_atoms(atoms: &[(&str, [f64; 3])]) -> Vec<Atom> {
+    atoms
+        .iter()
+        .map(|(symbol, [x, y, z])| Atom {
+            symbol: symbol.to_string(),
+            mass: Mass::amu(if *symbol == "H" { 1.008 } else { 12.011 }),
+            can_mv: true,
+            pos: Position::new(*x, *y, *z),
+            vel: Velocity::new(0.0, 0.0, 0.0),
+            force: Force::new(0.0, 0.0, 0.0),
+            next_force: Force::new(0.0, 0.0, 0.0),
+        })
+        .collect()
+}
@@ -1,15 +1,27 @@
 //external imports
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+//internal imports
+use crate::integrator::IntegratorKind;
+use crate::logger::Verbosity;
+use crate::report::EnergyFormat;
+use crate::schedule::Schedule;
+use crate::selection::Selection;
+use crate::thermostat::ThermostatKind;
+use crate::trajectory::TrajFormat;
+use crate::units::EnergyUnit;
+use crate::watchdog::TripAction;
 
 ///Command line arguments to be used by the program
 ///options must include the Gaussian16 input file.
 ///The program may be setup such that a simulation can
 ///restart from a particular time step.
 #[derive(Parser, Debug)]
+#[clap(subcommand_negates_reqs = true)]
 pub struct Args {
     ///input file name
-    #[clap(value_name = "INPUT")]
-    pub input: String,
+    #[clap(value_name = "INPUT", required = true)]
+    pub input: Option<String>,
 
     ///time step to be used in fs
     #[clap(short, long, default_value_t = 1.0)]
@@ -25,7 +37,333 @@ pub struct Args {
     #[clap(short, long, default_value_t = 10000)]
     pub num_steps: usize,
 
-    ///set atoms to be frozen during a simulation
-    #[clap(short, long)]
-    pub freeze: Option<String>,
+    ///run the stages of a YAML protocol file, each in its own directory
+    #[clap(long, value_name = "FILE")]
+    pub protocol: Option<String>,
+
+    ///set atoms to be frozen during a simulation, a selection such as
+    ///"1-4,6", "element H" or "within 3.0 of 12 and not element C"
+    #[clap(short, long, value_name = "ATOMS")]
+    pub freeze: Option<Selection>,
+
+    ///name a selection for use in other selections, NAME=ATOMS,
+    ///may be repeated and refer to groups named before it
+    #[clap(long, value_name = "NAME=ATOMS", multiple_occurrences = true)]
+    pub group: Vec<String>,
+
+    ///hold the lengths of all bonds to hydrogen fixed with SHAKE/RATTLE
+    #[clap(long)]
+    pub constrain_h: bool,
+
+    ///hold the distances between every atom of one selection and every
+    ///atom of another fixed, ATOMS:ATOMS such as 1:2, may be repeated
+    #[clap(long, value_name = "ATOMS:ATOMS", multiple_occurrences = true)]
+    pub constrain: Vec<String>,
+
+    ///relative tolerance of the constrained bond lengths
+    #[clap(long, value_name = "TOL", default_value_t = 1e-6)]
+    pub shake_tolerance: f64,
+
+    ///hold atoms in place along some axes, ATOMS:AXES such as 1-8:z,
+    ///may be repeated
+    #[clap(long, value_name = "ATOMS:AXES", multiple_occurrences = true)]
+    pub fix_axes: Vec<String>,
+
+    ///keep the centre of mass of a group of atoms in place while the
+    ///atoms move about it, may be repeated
+    #[clap(long, value_name = "ATOMS", multiple_occurrences = true)]
+    pub fix_centroid: Vec<Selection>,
+
+    ///move a group of atoms as a rigid body, may be repeated
+    #[clap(long, value_name = "ATOMS", multiple_occurrences = true)]
+    pub rigid: Vec<Selection>,
+
+    ///tether atoms to their input positions with a harmonic restraint,
+    ///ATOMS:K with K in the energy unit per A^2, may be repeated
+    #[clap(long, value_name = "ATOMS:K", multiple_occurrences = true)]
+    pub restrain: Vec<String>,
+
+    ///YAML file with a `restraints` section of distance, angle and
+    ///dihedral restraints
+    #[clap(long, value_name = "FILE")]
+    pub restraints: Option<String>,
+
+    ///YAML file with a `colvars` section of collective variables,
+    ///written to colvar.txt every step
+    #[clap(long, value_name = "FILE")]
+    pub colvars: Option<String>,
+
+    ///well-tempered metadynamics on one or two collective variables,
+    ///NAME[,NAME] of the --colvars
+    #[clap(
+        long,
+        value_name = "NAMES",
+        use_value_delimiter = true,
+        requires_all = &["colvars", "metad-height", "metad-width"]
+    )]
+    pub metad: Vec<String>,
+
+    ///initial hill height in the output energy unit
+    #[clap(long, value_name = "ENERGY")]
+    pub metad_height: Option<f64>,
+
+    ///hill width along each biased variable, in A, degrees or as a number
+    #[clap(long, value_name = "WIDTHS", use_value_delimiter = true)]
+    pub metad_width: Vec<f64>,
+
+    ///steps between hills
+    #[clap(long, value_name = "N", default_value_t = 50)]
+    pub metad_pace: usize,
+
+    ///bias factor of well-tempered metadynamics
+    #[clap(long, value_name = "GAMMA", default_value_t = 10.0)]
+    pub bias_factor: f64,
+
+    ///temperature of the system in K scaling the hill heights
+    #[clap(long, value_name = "K", default_value_t = 300.0)]
+    pub metad_temperature: f64,
+
+    ///umbrella sampling along a collective variable, NAME:START:END:N
+    ///with N windows evenly spaced from START to END in the units of colvar.txt
+    #[clap(
+        long,
+        value_name = "NAME:START:END:N",
+        requires_all = &["colvars", "umbrella-k"],
+        conflicts_with = "protocol"
+    )]
+    pub umbrella: Option<String>,
+
+    ///force constant of the umbrella windows in the output energy unit
+    ///per A^2 or rad^2
+    #[clap(long, value_name = "K")]
+    pub umbrella_k: Option<f64>,
+
+    ///run only this window, counted from 1, from the input structure
+    #[clap(long, value_name = "N", requires = "umbrella")]
+    pub umbrella_window: Option<usize>,
+
+    ///steered MD pulling a collective variable with a moving spring,
+    ///NAME:RATE with the rate in the units of colvar.txt per ps
+    #[clap(long, value_name = "NAME:RATE", requires_all = &["colvars", "steer-k"])]
+    pub steer: Option<String>,
+
+    ///force constant of the pulling spring in the output energy unit
+    ///per A^2 or rad^2
+    #[clap(long, value_name = "K")]
+    pub steer_k: Option<f64>,
+
+    ///confining flat-bottom wall, RADII:K with one radius or three
+    ///semi-axes X,Y,Z in A and K in the energy unit per A^2
+    #[clap(long, value_name = "RADII:K")]
+    pub wall: Option<String>,
+
+    ///fixed centre of the wall X,Y,Z in A, defaults to the centre of mass
+    #[clap(long, value_name = "X,Y,Z", requires = "wall")]
+    pub wall_centre: Option<String>,
+
+    ///formats of the trajectory, several may be given separated by commas
+    #[clap(long, arg_enum, use_value_delimiter = true, default_value = "xyz")]
+    pub traj_format: Vec<TrajFormat>,
+
+    ///unit of the energies and forces written to reports
+    #[clap(long, arg_enum, default_value = "kj-mol")]
+    pub energy_unit: EnergyUnit,
+
+    ///format of the energy report: text (energy.txt), csv (energy.csv)
+    ///or json (energy.json, one object per line)
+    #[clap(long, arg_enum, default_value = "text")]
+    pub energy_format: EnergyFormat,
+
+    ///write the trajectory every N steps, 0 disables
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub traj_every: usize,
+
+    ///write energy.txt every N steps, 0 disables
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub energy_every: usize,
+
+    ///write velocity.txt every N steps, 0 disables
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub vel_every: usize,
+
+    ///write per atom kinetic energy to kinetic.txt every N steps, 0 disables
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub kin_every: usize,
+
+    ///write force.txt every N steps, 0 disables
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub force_every: usize,
+
+    ///amount of progress information printed to the terminal
+    #[clap(long, arg_enum, default_value = "normal")]
+    pub verbosity: Verbosity,
+
+    ///write a JSON lines log of every step to the given file
+    #[clap(long, value_name = "FILE")]
+    pub log_file: Option<String>,
+
+    ///integration scheme
+    #[clap(long, arg_enum, default_value = "velocity-verlet")]
+    pub integrator: IntegratorKind,
+
+    ///collision frequency of the baoab Langevin integrator in 1/ps
+    #[clap(long, value_name = "1/PS", default_value_t = 1.0)]
+    pub friction: f64,
+
+    ///adapt the time step so that no atom moves further than this in A per step
+    #[clap(long, value_name = "A")]
+    pub max_displacement: Option<f64>,
+
+    ///adapt the time step so that the conserved energy changes by no more
+    ///than this per step, in the output energy unit
+    #[clap(long, value_name = "ENERGY")]
+    pub max_energy_error: Option<f64>,
+
+    ///lower bound of the adaptive time step in fs
+    #[clap(long, value_name = "FS", default_value_t = 0.01)]
+    pub min_time_step: f64,
+
+    ///upper bound of the adaptive time step in fs, defaults to --time-step
+    #[clap(long, value_name = "FS")]
+    pub max_time_step: Option<f64>,
+
+    ///Gaussian16 configuration of a cheap method driving the inner steps
+    ///of two level multiple time stepping (RESPA)
+    #[clap(long, value_name = "FILE")]
+    pub cheap_config: Option<String>,
+
+    ///inner steps of the cheap method per --time-step
+    #[clap(long, value_name = "N", default_value_t = 4)]
+    pub respa_ratio: usize,
+
+    ///thermostat driving the temperature along the --schedule
+    #[clap(long, arg_enum, requires = "schedule")]
+    pub thermostat: Option<ThermostatKind>,
+
+    ///thermostat coupling time in fs
+    #[clap(long, value_name = "FS", default_value_t = 100.0)]
+    pub tau: f64,
+
+    ///target temperature in K, or a schedule: linear:START:END,
+    ///stepwise:T1,T2,...:HOLD, exponential:START:END or cyclic:LOW:HIGH:PERIOD
+    #[clap(long, value_name = "SCHEDULE", required_if_eq("integrator", "baoab"))]
+    pub schedule: Option<Schedule>,
+
+    ///remove the overall linear and angular momentum every N steps,
+    ///0 disables. Skipped when atoms are frozen
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    pub remove_momentum: usize,
+
+    ///stop once the conserved energy drifts this far from step 0,
+    ///in the output energy unit
+    #[clap(long, value_name = "ENERGY")]
+    pub max_drift: Option<f64>,
+
+    ///stop once the conserved energy changes this much in a single step,
+    ///in the output energy unit
+    #[clap(long, value_name = "ENERGY")]
+    pub max_jump: Option<f64>,
+
+    ///stop once the force on any atom exceeds this value,
+    ///in the output energy unit per A
+    #[clap(long, value_name = "FORCE")]
+    pub max_force: Option<f64>,
+
+    ///stop once any two atoms come closer than this distance in A
+    #[clap(long, value_name = "A")]
+    pub min_distance: Option<f64>,
+
+    ///stop once the temperature exceeds this value in K
+    #[clap(long, value_name = "K")]
+    pub max_temperature: Option<f64>,
+
+    ///action taken when a guard trips
+    #[clap(long, arg_enum, default_value = "stop")]
+    pub on_trip: TripAction,
+
+    ///number of times a step is retried with a halved time step
+    ///before a rollback gives up and stops
+    #[clap(long, value_name = "N", default_value_t = 3)]
+    pub max_retries: usize,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+///Analysis tools run instead of a simulation.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    ///convert a binary trajectory (dcd, xtc) into plain XYZ
+    Convert {
+        ///trajectory file to convert
+        #[clap(value_name = "TRAJECTORY")]
+        input: String,
+
+        ///topology supplying the element symbols
+        #[clap(long, default_value = "topology.pdb")]
+        top: String,
+
+        ///XYZ file to write
+        #[clap(short, long, default_value = "converted.xyz")]
+        output: String,
+    },
+    ///rebuild the free energy surface of a metadynamics run from its HILLS file
+    Fes {
+        ///HILLS file of the run
+        #[clap(value_name = "HILLS", default_value = "HILLS")]
+        input: String,
+
+        ///grid points along each collective variable
+        #[clap(long, default_value_t = 100)]
+        bins: usize,
+
+        ///file to write the free energy to
+        #[clap(short, long, default_value = "fes.txt")]
+        output: String,
+    },
+    ///combine umbrella windows into the potential of mean force with WHAM
+    Wham {
+        ///umbrella.txt files of the windows
+        #[clap(value_name = "WINDOWS", required = true)]
+        inputs: Vec<String>,
+
+        ///bins along the collective variable
+        #[clap(long, default_value_t = 100)]
+        bins: usize,
+
+        ///temperature of the windows in K
+        #[clap(long, default_value_t = 300.0)]
+        temperature: f64,
+
+        ///samples of every window left out as equilibration
+        #[clap(long, default_value_t = 0)]
+        skip: usize,
+
+        ///bootstrap resamplings for the errors, 0 for none
+        #[clap(long, default_value_t = 100)]
+        bootstrap: usize,
+
+        ///file to write the potential of mean force to
+        #[clap(short, long, default_value = "pmf.txt")]
+        output: String,
+    },
+    ///estimate the free energy along a pulled variable from the work of
+    ///several steered runs with Jarzynski's equality
+    Jarzynski {
+        ///steered.txt files of the pulling runs
+        #[clap(value_name = "RUNS", required = true)]
+        inputs: Vec<String>,
+
+        ///temperature of the runs in K
+        #[clap(long, default_value_t = 300.0)]
+        temperature: f64,
+
+        ///centres of the spring the free energy is given at
+        #[clap(long, default_value_t = 100)]
+        points: usize,
+
+        ///file to write the free energy to
+        #[clap(short, long, default_value = "jarzynski.txt")]
+        output: String,
+    },
 }
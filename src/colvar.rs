@@ -0,0 +1,482 @@
+use std::collections::BTreeSet;
+use std::f64::consts::PI;
+use std::fs::File;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::geometry;
+use crate::restraint::Coordinate;
+use crate::selection::{Selection, Selector};
+use crate::units;
+use crate::vector::Vector3D;
+use crate::vectored::{Force, Vectored};
+
+type Vector = Vector3D<f64>;
+
+///A collective variable as written in a colvar file, groups of atoms are
+///selections and single atoms are counted from 1.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ColvarDefinition {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: DefinitionKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DefinitionKind {
+    Distance {
+        atoms: Vec<usize>,
+    },
+    Angle {
+        atoms: Vec<usize>,
+    },
+    Dihedral {
+        atoms: Vec<usize>,
+    },
+    ///smooth count of the pairs between the groups closer than `r0` in A
+    Coordination {
+        group_a: String,
+        group_b: String,
+        r0: f64,
+        #[serde(default = "default_n")]
+        n: i32,
+        #[serde(default = "default_m")]
+        m: i32,
+    },
+    ///distance between the centres of mass of the groups
+    ComDistance {
+        group_a: String,
+        group_b: String,
+    },
+    ///RMSD of the atoms to a reference XYZ file after superposition,
+    ///to the input geometry when no reference is given
+    Rmsd {
+        atoms: String,
+        reference: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ColvarFile {
+    colvars: Vec<ColvarDefinition>,
+}
+
+///A collective variable of the atoms, evaluated with its gradient.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Colvar {
+    pub name: String,
+    kind: Kind,
+}
+
+//atoms counted from 0
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+enum Kind {
+    Internal {
+        coordinate: Coordinate,
+        atoms: Vec<usize>,
+    },
+    Coordination {
+        pairs: Vec<(usize, usize)>,
+        r0: f64,
+        n: i32,
+        m: i32,
+    },
+    ComDistance {
+        group_a: Vec<usize>,
+        group_b: Vec<usize>,
+    },
+    //reference centred on its centroid
+    Rmsd {
+        atoms: Vec<usize>,
+        reference: Vec<Vector>,
+    },
+}
+
+impl Colvar {
+    ///collective variables of the `colvars` section of a YAML file.
+    pub fn load(path: &str, atoms: &[Atom], selector: &Selector) -> Result<Vec<Colvar>> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+        let contents: ColvarFile = serde_yaml::from_reader(file)
+            .with_context(|| format!("failed to read collective variables from {}", path))?;
+        let mut names = BTreeSet::new();
+        contents
+            .colvars
+            .iter()
+            .map(|x| {
+                if !names.insert(x.name.as_str()) {
+                    bail!("collective variable \"{}\" is defined twice", x.name);
+                }
+                Self::new(x, atoms, selector)
+                    .with_context(|| format!("in collective variable \"{}\"", x.name))
+            })
+            .collect()
+    }
+
+    fn new(x: &ColvarDefinition, atoms: &[Atom], selector: &Selector) -> Result<Colvar> {
+        let select = |text: &str| selector.select(&text.parse::<Selection>()?);
+        let internal = |coordinate: Coordinate, indices: &[usize]| {
+            Ok::<Kind, anyhow::Error>(Kind::Internal {
+                coordinate,
+                atoms: coordinate.indices(indices, atoms.len())?,
+            })
+        };
+        let kind = match &x.kind {
+            DefinitionKind::Distance { atoms } => internal(Coordinate::Distance, atoms)?,
+            DefinitionKind::Angle { atoms } => internal(Coordinate::Angle, atoms)?,
+            DefinitionKind::Dihedral { atoms } => internal(Coordinate::Dihedral, atoms)?,
+            DefinitionKind::Coordination {
+                group_a,
+                group_b,
+                r0,
+                n,
+                m,
+            } => {
+                if *r0 <= 0.0 || *n <= 0 || *m <= *n {
+                    bail!("a coordination number needs r0 > 0 and m > n > 0");
+                }
+                let (group_a, group_b) = (select(group_a)?, select(group_b)?);
+                let pairs: BTreeSet<(usize, usize)> = group_a
+                    .iter()
+                    .flat_map(|i| group_b.iter().map(move |j| (*i.min(j), *i.max(j))))
+                    .filter(|(i, j)| i != j)
+                    .collect();
+                if pairs.is_empty() {
+                    bail!("the groups have no pairs of distinct atoms");
+                }
+                Kind::Coordination {
+                    pairs: pairs.into_iter().collect(),
+                    r0: *r0,
+                    n: *n,
+                    m: *m,
+                }
+            }
+            DefinitionKind::ComDistance { group_a, group_b } => Kind::ComDistance {
+                group_a: select(group_a)?,
+                group_b: select(group_b)?,
+            },
+            DefinitionKind::Rmsd {
+                atoms: selection,
+                reference,
+            } => {
+                let indices = select(selection)?;
+                if indices.len() < 3 {
+                    bail!("an RMSD needs at least three atoms");
+                }
+                let all = match reference {
+                    Some(path) => read_xyz(path, atoms.len())?,
+                    None => atoms.iter().map(|x| x.pos.as_vec()).collect(),
+                };
+                let reference: Vec<Vector> = indices.iter().map(|x| all[*x]).collect();
+                Kind::Rmsd {
+                    atoms: indices,
+                    reference: centred(&reference),
+                }
+            }
+        };
+        Ok(Colvar {
+            name: x.name.clone(),
+            kind,
+        })
+    }
+
+    ///value in A, rad or as a number, and its gradient with respect to
+    ///the position of every atom.
+    pub fn evaluate(&self, atoms: &[Atom]) -> (f64, Vec<Vector>) {
+        let zero = Vector3D::new(0.0, 0.0, 0.0);
+        let mut gradient = vec![zero; atoms.len()];
+        let position = |x: usize| atoms[x].pos.as_vec();
+        let value = match &self.kind {
+            Kind::Internal { coordinate, atoms } => {
+                let positions: Vec<Vector> = atoms.iter().map(|x| position(*x)).collect();
+                let (value, partial) = coordinate.evaluate(&positions);
+                for (index, g) in atoms.iter().zip(partial) {
+                    gradient[*index] = g;
+                }
+                value
+            }
+            Kind::Coordination { pairs, r0, n, m } => {
+                let mut value = 0.0;
+                for &(i, j) in pairs {
+                    let (r, unit) = geometry::distance(position(i), position(j));
+                    let (s, ds) = switching(r / r0, *n, *m);
+                    value += s;
+                    gradient[i] = gradient[i] + unit[0] * (ds / r0);
+                    gradient[j] = gradient[j] + unit[1] * (ds / r0);
+                }
+                value
+            }
+            Kind::ComDistance { group_a, group_b } => {
+                let (a, mass_a) = centre_of_mass(atoms, group_a);
+                let (b, mass_b) = centre_of_mass(atoms, group_b);
+                let (value, unit) = geometry::distance(a, b);
+                for &i in group_a {
+                    gradient[i] = gradient[i] + unit[0] * (atoms[i].mass.as_amu() / mass_a);
+                }
+                for &i in group_b {
+                    gradient[i] = gradient[i] + unit[1] * (atoms[i].mass.as_amu() / mass_b);
+                }
+                value
+            }
+            Kind::Rmsd {
+                atoms: indices,
+                reference,
+            } => {
+                let current: Vec<Vector> = indices.iter().map(|x| position(*x)).collect();
+                let current = centred(&current);
+                let rotation = geometry::fit(&current, reference);
+                let deviations: Vec<Vector> = current
+                    .iter()
+                    .zip(reference)
+                    .map(|(x, y)| *x - geometry::rotate(&rotation, *y))
+                    .collect();
+                let count = indices.len() as f64;
+                let value = (deviations.iter().map(|x| x.dot(*x)).sum::<f64>() / count).sqrt();
+                if value > 0.0 {
+                    for (index, deviation) in indices.iter().zip(deviations) {
+                        gradient[*index] = deviation / (count * value);
+                    }
+                }
+                value
+            }
+        };
+        (value, gradient)
+    }
+
+    ///whether the value wraps around, as dihedrals do within (-pi, pi].
+    pub fn periodic(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Internal {
+                coordinate: Coordinate::Dihedral,
+                ..
+            }
+        )
+    }
+
+    ///difference of two values, periodic ones taken through the shortest turn.
+    pub fn difference(&self, value: f64, reference: f64) -> f64 {
+        match &self.kind {
+            Kind::Internal { coordinate, .. } => coordinate.difference(value, reference),
+            _ => value - reference,
+        }
+    }
+
+    ///factor and unit of the value as written out, angles in degrees.
+    pub fn display(&self) -> (f64, &'static str) {
+        match &self.kind {
+            Kind::Internal {
+                coordinate: Coordinate::Angle | Coordinate::Dihedral,
+                ..
+            } => (180.0 / PI, "deg"),
+            Kind::Coordination { .. } => (1.0, ""),
+            _ => (1.0, "A"),
+        }
+    }
+}
+
+///Harmonic bias k/2 (s - c)^2 on a collective variable, the umbrella of
+///a window or the spring of steered MD.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Harmonic {
+    pub colvar: Colvar,
+    ///centre in the internal units of the variable
+    pub centre: f64,
+    ///force constant in kJ/mol per A^2, rad^2 or unit of the variable squared
+    pub force_constant: f64,
+}
+
+impl Harmonic {
+    ///add the bias forces in amu A/fs^2 to `forces`, returning the bias
+    ///energy in kJ/mol.
+    pub fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        let (value, gradient) = self.colvar.evaluate(atoms);
+        let slope = -self.force(value);
+        for (force, g) in forces.iter_mut().zip(gradient) {
+            let f = g * (-slope / units::MD_ENERGY_KJ_MOL);
+            *force = *force + Force::new(f.x, f.y, f.z);
+        }
+        self.energy(value, self.centre)
+    }
+
+    ///energy in kJ/mol at a value of the variable with the bias centred at `centre`.
+    pub fn energy(&self, value: f64, centre: f64) -> f64 {
+        let delta = self.colvar.difference(value, centre);
+        0.5 * self.force_constant * delta * delta
+    }
+
+    ///force along the variable at a value, in kJ/mol per internal unit.
+    pub fn force(&self, value: f64) -> f64 {
+        -self.force_constant * self.colvar.difference(value, self.centre)
+    }
+}
+
+//rational switching function (1 - x^n) / (1 - x^m) and its derivative in x,
+//taken to its limit at x = 1, where numerator and denominator vanish.
+fn switching(x: f64, n: i32, m: i32) -> (f64, f64) {
+    let (n_f, m_f) = (n as f64, m as f64);
+    if (x - 1.0).abs() < 1e-6 {
+        return (n_f / m_f, n_f * (n_f - m_f) / (2.0 * m_f));
+    }
+    let (xn, xm) = (x.powi(n), x.powi(m));
+    let value = (1.0 - xn) / (1.0 - xm);
+    //x^(n-1) rather than x^n / x, finite for coincident atoms at x = 0
+    let derivative =
+        (-n_f * x.powi(n - 1) * (1.0 - xm) + m_f * x.powi(m - 1) * (1.0 - xn)) / (1.0 - xm).powi(2);
+    (value, derivative)
+}
+
+fn centre_of_mass(atoms: &[Atom], group: &[usize]) -> (Vector, f64) {
+    let mass: f64 = group.iter().map(|x| atoms[*x].mass.as_amu()).sum();
+    let centre = group.iter().fold(Vector3D::new(0.0, 0.0, 0.0), |a, x| {
+        a + atoms[*x].mass.as_amu() * atoms[*x].pos.as_vec()
+    }) / mass;
+    (centre, mass)
+}
+
+//points moved onto their centroid.
+fn centred(points: &[Vector]) -> Vec<Vector> {
+    let centroid = points
+        .iter()
+        .fold(Vector3D::new(0.0, 0.0, 0.0), |a, x| a + *x)
+        / points.len() as f64;
+    points.iter().map(|x| *x - centroid).collect()
+}
+
+//positions of an XYZ file holding a single frame of `count` atoms.
+fn read_xyz(path: &str, count: usize) -> Result<Vec<Vector>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to open {}", path))?;
+    let positions = contents
+        .lines()
+        .skip(2)
+        .filter(|x| !x.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let coords = fields
+                .get(1..4)
+                .with_context(|| format!("invalid line \"{}\" in {}", line, path))?
+                .iter()
+                .map(|x| x.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .with_context(|| format!("invalid line \"{}\" in {}", line, path))?;
+            Ok(Vector3D::new(coords[0], coords[1], coords[2]))
+        })
+        .collect::<Result<Vec<Vector>>>()?;
+    if positions.len() != count {
+        bail!(
+            "{} holds {} atoms, the simulation {}",
+            path,
+            positions.len(),
+            count
+        );
+    }
+    Ok(positions)
+}
+
+fn default_n() -> i32 {
+    6
+}
+
+fn default_m() -> i32 {
+    12
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::geometry::check_gradient;
+    use crate::vectored::Position;
+
+    fn points() -> Vec<Vector> {
+        [
+            [0.0, 0.0, 0.0],
+            [1.1, 0.2, -0.1],
+            [-0.4, 1.3, 0.5],
+            [2.0, -1.0, 1.2],
+            [0.3, 0.6, -1.4],
+        ]
+        .map(|[x, y, z]| Vector3D::new(x, y, z))
+        .to_vec()
+    }
+
+    //the variable evaluated on atoms placed at the points
+    fn evaluate(colvar: &Colvar) -> impl Fn(&[Vector]) -> (f64, Vec<Vector>) + '_ {
+        move |points| {
+            let mut atoms = test_atoms(&[("C", [0.0; 3]), ("H", [0.0; 3]), ("H", [0.0; 3])]);
+            atoms.extend(test_atoms(&[("C", [0.0; 3]), ("C", [0.0; 3])]));
+            for (atom, p) in atoms.iter_mut().zip(points) {
+                atom.pos = Position::new(p.x, p.y, p.z);
+            }
+            colvar.evaluate(&atoms)
+        }
+    }
+
+    fn colvar(kind: Kind) -> Colvar {
+        Colvar {
+            name: "test".to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn coordination_gradient() {
+        let coordination = colvar(Kind::Coordination {
+            pairs: vec![(0, 1), (0, 2), (1, 3), (2, 4), (3, 4)],
+            r0: 1.2,
+            n: 6,
+            m: 12,
+        });
+        check_gradient(evaluate(&coordination), &points(), None);
+        //a pair at exactly r0 and a coincident pair
+        let mut special = points();
+        special[1] = Vector3D::new(1.2, 0.0, 0.0);
+        special[3] = special[1];
+        let (value, gradient) = evaluate(&coordination)(&special);
+        assert!(value.is_finite() && gradient.iter().all(|x| x.dot(*x).is_finite()));
+    }
+
+    #[test]
+    fn com_distance_gradient() {
+        let com = colvar(Kind::ComDistance {
+            group_a: vec![0, 1, 2],
+            group_b: vec![3, 4],
+        });
+        check_gradient(evaluate(&com), &points(), None);
+    }
+
+    #[test]
+    fn rmsd_gradient() {
+        let reference = [
+            [0.1, -0.1, 0.2],
+            [1.0, 0.4, 0.0],
+            [-0.5, 1.0, 0.7],
+            [2.2, -0.8, 1.0],
+        ]
+        .map(|[x, y, z]| Vector3D::new(x, y, z));
+        let rmsd = colvar(Kind::Rmsd {
+            atoms: vec![0, 1, 2, 3],
+            reference: centred(&reference),
+        });
+        assert!(evaluate(&rmsd)(&points()).0 > 0.0);
+        check_gradient(evaluate(&rmsd), &points(), None);
+    }
+
+    #[test]
+    fn switching_limits() {
+        for (n, m) in [(1, 2), (6, 12), (8, 14)] {
+            let (value, derivative) = switching(0.0, n, m);
+            assert_eq!(value, 1.0);
+            assert_eq!(derivative, if n == 1 { -1.0 } else { 0.0 });
+            for x in [1.0, 1.0 + 1e-7, 1.0 - 2e-6, 0.5, 2.0] {
+                let (value, derivative) = switching(x, n, m);
+                let h = 1e-5;
+                let numeric = (switching(x + h, n, m).0 - switching(x - h, n, m).0) / (2.0 * h);
+                assert!(value.is_finite() && (derivative - numeric).abs() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::atom::{self, Atom};
+use crate::momentum;
+use crate::quantity::Time;
+use crate::vector::Vector3D;
+use crate::vectored::{Displacement, Position, Vectored, Velocity};
+
+//iterations of SHAKE and RATTLE before giving up on convergence
+const MAX_ITERATIONS: usize = 1000;
+//atoms of a rigid group within this distance in A of a line or plane
+//through it are taken to lie on it
+const FLAT_DISTANCE: f64 = 0.05;
+
+///A fixed distance in A between two atoms, indices from 0.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Constraint {
+    pub i: usize,
+    pub j: usize,
+    pub length: f64,
+    ///constraint force of the last step along the bond in amu A/fs^2,
+    ///positive when it pushes the atoms apart
+    #[serde(default)]
+    pub force: f64,
+}
+
+///Atoms held in place along some of the x, y and z axes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AxisFix {
+    pub atoms: Vec<usize>,
+    pub axes: [bool; 3],
+}
+
+impl AxisFix {
+    ///`axes` holds the letters of the fixed axes, such as `z` or `xy`.
+    pub fn new(atoms: Vec<usize>, axes: &str) -> Result<AxisFix> {
+        let mut fixed = [false; 3];
+        for letter in axes.trim().chars() {
+            match letter.to_ascii_lowercase() {
+                'x' => fixed[0] = true,
+                'y' => fixed[1] = true,
+                'z' => fixed[2] = true,
+                _ => bail!("invalid axes \"{}\", expected letters of x, y and z", axes),
+            }
+        }
+        if !fixed.contains(&true) {
+            bail!("no axis given to fix");
+        }
+        Ok(AxisFix { atoms, axes: fixed })
+    }
+
+    fn count(&self) -> usize {
+        self.atoms.len() * self.axes.iter().filter(|x| **x).count()
+    }
+}
+
+///Distance constraints held by SHAKE for the positions and RATTLE for
+///the velocities, Andersen, J. Comput. Phys. 52, 24 (1983). Beside them
+///atoms may be fixed along axes and groups may keep their centre of mass
+///in place. Rigid groups are held by constraints between all their pairs.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Constraints {
+    pub bonds: Vec<Constraint>,
+    ///relative tolerance of the constrained lengths
+    pub tolerance: f64,
+    #[serde(default)]
+    pub axes: Vec<AxisFix>,
+    ///groups whose centre of mass stays in place
+    #[serde(default)]
+    pub centroids: Vec<Vec<usize>>,
+    ///groups moving as rigid bodies
+    #[serde(default)]
+    pub rigid: Vec<Vec<usize>>,
+}
+
+impl Constraints {
+    ///constraints on all bonds to hydrogen, when `hydrogen` is set, and on
+    ///the given pairs of atoms counted from 0. The lengths are taken from
+    ///the current geometry, pairs of two frozen atoms are skipped.
+    pub fn new(
+        atoms: &[Atom],
+        hydrogen: bool,
+        pairs: &[(usize, usize)],
+        tolerance: f64,
+    ) -> Result<Constraints> {
+        if tolerance <= 0.0 || !tolerance.is_finite() {
+            bail!("the SHAKE tolerance must be positive, not {}", tolerance);
+        }
+        let mut list = vec![];
+        if hydrogen {
+            list.extend(
+                atom::perceive_bonds(atoms)
+                    .into_iter()
+                    .filter(|(i, j)| atoms[*i].symbol == "H" || atoms[*j].symbol == "H"),
+            );
+        }
+        list.extend(pairs);
+        let mut bonds: Vec<Constraint> = vec![];
+        for (i, j) in list {
+            let (i, j) = (i.min(j), i.max(j));
+            let known = bonds.iter().any(|x| x.i == i && x.j == j);
+            if known || (!atoms[i].can_mv && !atoms[j].can_mv) {
+                continue;
+            }
+            bonds.push(Constraint {
+                i,
+                j,
+                length: (atoms[i].pos - atoms[j].pos).norm(),
+                force: 0.0,
+            });
+        }
+        Ok(Constraints {
+            bonds,
+            tolerance,
+            ..Default::default()
+        })
+    }
+
+    ///add the fixes of axes, centroids and rigid bodies. An atom may be
+    ///in one of them only and must not be frozen, atoms held along axes
+    ///or by a centroid cannot be in a distance constraint. Distance
+    ///constraints leave a linear group of more than two atoms and a planar
+    ///one of more than three flexible, so these are rejected.
+    pub fn restrict(
+        mut self,
+        atoms: &[Atom],
+        axes: Vec<AxisFix>,
+        centroids: Vec<Vec<usize>>,
+        rigid: Vec<Vec<usize>>,
+    ) -> Result<Constraints> {
+        let mut seen = vec![false; atoms.len()];
+        let groups = axes
+            .iter()
+            .map(|x| &x.atoms)
+            .chain(centroids.iter())
+            .chain(rigid.iter());
+        for group in groups {
+            if group.is_empty() {
+                bail!("an atom group to fix is empty");
+            }
+            for &index in group {
+                if !atoms[index].can_mv {
+                    bail!("atom {} is frozen and cannot be fixed again", index + 1);
+                }
+                if seen[index] {
+                    bail!("atom {} is in more than one fixed group", index + 1);
+                }
+                seen[index] = true;
+            }
+        }
+        for group in &rigid {
+            let degenerate = match dimension(&positions(atoms, group)) {
+                1 => group.len() > 2,
+                2 => group.len() > 3,
+                _ => false,
+            };
+            if degenerate {
+                bail!(
+                    "the rigid group of atoms {:?} is linear or planar and cannot be held rigid",
+                    group.iter().map(|x| x + 1).collect::<Vec<usize>>()
+                );
+            }
+            for (n, &i) in group.iter().enumerate() {
+                for &j in &group[n + 1..] {
+                    let (i, j) = (i.min(j), i.max(j));
+                    self.bonds.retain(|x| x.i != i || x.j != j);
+                    self.bonds.push(Constraint {
+                        i,
+                        j,
+                        length: (atoms[i].pos - atoms[j].pos).norm(),
+                        force: 0.0,
+                    });
+                }
+            }
+        }
+        let held = axes.iter().map(|x| &x.atoms).chain(centroids.iter());
+        for &index in held.flatten() {
+            if self.bonds.iter().any(|x| x.i == index || x.j == index) {
+                bail!(
+                    "atom {} is held in place and cannot be in a distance constraint",
+                    index + 1
+                );
+            }
+        }
+        self.axes = axes;
+        self.centroids = centroids;
+        self.rigid = rigid;
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bonds.is_empty()
+            && self.axes.is_empty()
+            && self.centroids.is_empty()
+            && self.rigid.is_empty()
+    }
+
+    ///true when the fixes hold the system in place, so that the total
+    ///momentum is not conserved.
+    pub fn anchors(&self) -> bool {
+        !self.axes.is_empty() || !self.centroids.is_empty()
+    }
+
+    ///degrees of freedom taken out of the atoms. The pair constraints of
+    ///a rigid body are redundant, it keeps three translations and its rotations.
+    pub fn removed_dof(&self, atoms: &[Atom]) -> usize {
+        let axes: usize = self.axes.iter().map(AxisFix::count).sum();
+        let in_body = |x: &&Constraint| {
+            self.rigid
+                .iter()
+                .any(|g| g.contains(&x.i) && g.contains(&x.j))
+        };
+        let bonds = self.bonds.iter().filter(|x| !in_body(x)).count();
+        let rigid: usize = self
+            .rigid
+            .iter()
+            .map(|group| {
+                let group: Vec<Atom> = group.iter().map(|x| atoms[*x].clone()).collect();
+                (3 * group.len()).saturating_sub(3 + momentum::rotational_dof(&group))
+            })
+            .sum();
+        bonds + axes + 3 * self.centroids.len() + rigid
+    }
+
+    ///put the atoms moved over a step of `time_step` from `old` back onto
+    ///the fixed axes and centroids. As in SHAKE the velocities are
+    ///corrected by the same displacement over the step.
+    pub fn restrict_positions(&self, atoms: &mut [Atom], old: &[Position<f64>], time_step: Time) {
+        let moved: Vec<Position<f64>> = atoms.iter().map(|x| x.pos).collect();
+        for fix in &self.axes {
+            for &index in &fix.atoms {
+                let mut pos = [0.0; 3];
+                let (new, before) = (atoms[index].pos.as_vec(), old[index].as_vec());
+                for (k, value) in pos.iter_mut().enumerate() {
+                    *value = match fix.axes[k] {
+                        true => component(before, k),
+                        false => component(new, k),
+                    };
+                }
+                atoms[index].pos = Position::new(pos[0], pos[1], pos[2]);
+            }
+        }
+        for group in &self.centroids {
+            let weights = masses(atoms, group);
+            let before: Vec<Vector3D<f64>> = group.iter().map(|x| old[*x].as_vec()).collect();
+            let shift = centre(&before, &weights) - centre(&positions(atoms, group), &weights);
+            for &index in group {
+                atoms[index].pos = atoms[index].pos + Displacement::new(shift.x, shift.y, shift.z);
+            }
+        }
+        for (atom, moved) in atoms.iter_mut().zip(moved) {
+            atom.vel = atom.vel + (atom.pos - moved) / time_step;
+        }
+    }
+
+    ///remove the velocities along fixed axes and of fixed centroids.
+    pub fn restrict_velocities(&self, atoms: &mut [Atom]) {
+        for fix in &self.axes {
+            for &index in &fix.atoms {
+                let vec = atoms[index].vel.as_vec();
+                let keep = |k: usize| match fix.axes[k] {
+                    true => 0.0,
+                    false => component(vec, k),
+                };
+                atoms[index].vel = Velocity::new(keep(0), keep(1), keep(2));
+            }
+        }
+        for group in &self.centroids {
+            let weights = masses(atoms, group);
+            let velocities: Vec<Vector3D<f64>> =
+                group.iter().map(|x| atoms[*x].vel.as_vec()).collect();
+            let drift = centre(&velocities, &weights);
+            for &index in group {
+                atoms[index].vel = atoms[index].vel - Velocity::new(drift.x, drift.y, drift.z);
+            }
+        }
+    }
+
+    ///SHAKE the positions reached from `old` over a step of `time_step`
+    ///back onto the constraints. The velocities are corrected by the same
+    ///displacement over the step. false when it did not converge.
+    pub fn shake(&mut self, atoms: &mut [Atom], old: &[Position<f64>], time_step: Time) -> bool {
+        let dt = time_step.as_fs();
+        let mut multipliers = vec![0.0; self.bonds.len()];
+        let mut converged = false;
+        'iterations: for _ in 0..MAX_ITERATIONS {
+            converged = true;
+            for (bond, multiplier) in self.bonds.iter().zip(multipliers.iter_mut()) {
+                let (i, j) = (bond.i, bond.j);
+                let r = atoms[i].pos - atoms[j].pos;
+                let target = bond.length * bond.length;
+                let diff = target - r.sqr_norm();
+                if diff.abs() <= 2.0 * self.tolerance * target {
+                    continue;
+                }
+                converged = false;
+                let reference = old[i] - old[j];
+                let projection = r.as_vec().dot(reference.as_vec());
+                //the bond turned by a right angle or more over the step,
+                //moving along the old bond cannot restore its length
+                if projection <= 0.0 {
+                    break 'iterations;
+                }
+                let (wi, wj) = (inverse_mass(&atoms[i]), inverse_mass(&atoms[j]));
+                let g = diff / (2.0 * (wi + wj) * projection);
+                *multiplier += g;
+                move_pair(atoms, i, j, reference * g, time_step);
+            }
+            if converged {
+                break;
+            }
+        }
+        for (bond, multiplier) in self.bonds.iter_mut().zip(&multipliers) {
+            let reference = old[bond.i] - old[bond.j];
+            bond.force = 2.0 * multiplier * reference.norm() / (dt * dt);
+        }
+        converged
+    }
+
+    ///RATTLE the velocities so that no constrained distance changes. The
+    ///reported force becomes the mean of the forces SHAKE applied at the
+    ///start of the step and RATTLE at its end.
+    pub fn rattle(&mut self, atoms: &mut [Atom], time_step: Time) -> bool {
+        let dt = time_step.as_fs();
+        for bond in self.bonds.iter_mut() {
+            bond.force *= 0.5;
+        }
+        let mut converged = false;
+        for _ in 0..MAX_ITERATIONS {
+            converged = true;
+            for bond in self.bonds.iter_mut() {
+                let (i, j) = (bond.i, bond.j);
+                let r = atoms[i].pos - atoms[j].pos;
+                let v = atoms[i].vel - atoms[j].vel;
+                let rate = r.as_vec().dot(v.as_vec());
+                if rate.abs() <= self.tolerance * bond.length * bond.length / dt {
+                    continue;
+                }
+                converged = false;
+                let (wi, wj) = (inverse_mass(&atoms[i]), inverse_mass(&atoms[j]));
+                let k = rate / ((wi + wj) * r.sqr_norm());
+                let change = r.as_vec() * k;
+                let change = Velocity::new(change.x, change.y, change.z);
+                atoms[i].vel = atoms[i].vel - change * wi;
+                atoms[j].vel = atoms[j].vel + change * wj;
+                bond.force -= k * bond.length / dt;
+            }
+            if converged {
+                break;
+            }
+        }
+        converged
+    }
+}
+
+//dimension spanned by the points: 1 on a line, 2 in a plane and 3 otherwise.
+fn dimension(points: &[Vector3D<f64>]) -> usize {
+    let first = points[0];
+    let relative: Vec<Vector3D<f64>> = points.iter().map(|x| *x - first).collect();
+    let longest = |vectors: &[Vector3D<f64>]| {
+        vectors.iter().fold(Vector3D::new(0.0, 0.0, 0.0), |a, x| {
+            if x.dot(*x) > a.dot(a) {
+                *x
+            } else {
+                a
+            }
+        })
+    };
+    let axis = longest(&relative);
+    let length = axis.dot(axis).sqrt();
+    if length <= FLAT_DISTANCE {
+        return 1;
+    }
+    let axis = axis / length;
+    let off_axis: Vec<Vector3D<f64>> = relative.iter().map(|x| *x - axis * x.dot(axis)).collect();
+    let across = longest(&off_axis);
+    let width = across.dot(across).sqrt();
+    if width <= FLAT_DISTANCE {
+        return 1;
+    }
+    let normal = axis.cross(across / width);
+    match relative
+        .iter()
+        .all(|x| x.dot(normal).abs() <= FLAT_DISTANCE)
+    {
+        true => 2,
+        false => 3,
+    }
+}
+
+fn positions(atoms: &[Atom], group: &[usize]) -> Vec<Vector3D<f64>> {
+    group.iter().map(|x| atoms[*x].pos.as_vec()).collect()
+}
+
+//mass weighted mean of the vectors
+fn centre(vectors: &[Vector3D<f64>], weights: &[f64]) -> Vector3D<f64> {
+    let total: f64 = weights.iter().sum();
+    vectors
+        .iter()
+        .zip(weights)
+        .fold(Vector3D::new(0.0, 0.0, 0.0), |a, (x, w)| a + *w * *x)
+        / total
+}
+
+fn masses(atoms: &[Atom], group: &[usize]) -> Vec<f64> {
+    group.iter().map(|x| atoms[*x].mass.as_amu()).collect()
+}
+
+fn component(vec: Vector3D<f64>, k: usize) -> f64 {
+    match k {
+        0 => vec.x,
+        1 => vec.y,
+        _ => vec.z,
+    }
+}
+
+//1/m in 1/amu, zero for frozen atoms
+fn inverse_mass(atom: &Atom) -> f64 {
+    match atom.can_mv {
+        true => 1.0 / atom.mass.as_amu(),
+        false => 0.0,
+    }
+}
+
+//move atom i by step / m_i and atom j by -step / m_j, carrying the
+//displacement over the step into the velocities.
+fn move_pair(atoms: &mut [Atom], i: usize, j: usize, step: Displacement<f64>, dt: Time) {
+    for (index, sign) in [(i, 1.0), (j, -1.0)] {
+        let shift = step * (sign * inverse_mass(&atoms[index]));
+        atoms[index].pos = atoms[index].pos + shift;
+        atoms[index].vel = atoms[index].vel + shift / dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+
+    fn water() -> Vec<Atom> {
+        test_atoms(&[
+            ("O", [0.0, 0.0, 0.0]),
+            ("H", [0.96, 0.0, 0.0]),
+            ("H", [-0.24, 0.93, 0.0]),
+        ])
+    }
+
+    fn tetrahedron() -> Vec<Atom> {
+        test_atoms(&[
+            ("C", [0.0, 0.0, 0.0]),
+            ("H", [1.09, 0.0, 0.0]),
+            ("H", [-0.36, 1.03, 0.0]),
+            ("H", [-0.36, -0.51, 0.89]),
+            ("C", [0.0, 0.0, 3.0]),
+        ])
+    }
+
+    #[test]
+    fn shake_restores_lengths() {
+        let mut atoms = water();
+        let mut constraints = Constraints::new(&atoms, false, &[(0, 1), (0, 2)], 1e-6).unwrap();
+        let old: Vec<Position<f64>> = atoms.iter().map(|x| x.pos).collect();
+        let kicks = [
+            [0.02, -0.03, 0.01],
+            [0.05, 0.02, -0.04],
+            [-0.03, 0.04, 0.02],
+        ];
+        for (atom, [x, y, z]) in atoms.iter_mut().zip(kicks) {
+            atom.pos = atom.pos + Displacement::new(x, y, z);
+        }
+        assert!(constraints.shake(&mut atoms, &old, Time::fs(1.0)));
+        for bond in &constraints.bonds {
+            let length = (atoms[bond.i].pos - atoms[bond.j].pos).norm();
+            assert!(
+                (length / bond.length - 1.0).abs() < 2e-6,
+                "length {}",
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn rattle_removes_bond_velocities() {
+        let mut atoms = water();
+        let mut constraints = Constraints::new(&atoms, false, &[(0, 1), (0, 2)], 1e-8).unwrap();
+        let velocities = [
+            [0.01, -0.02, 0.005],
+            [-0.03, 0.01, 0.02],
+            [0.02, 0.03, -0.01],
+        ];
+        for (atom, [x, y, z]) in atoms.iter_mut().zip(velocities) {
+            atom.vel = Velocity::new(x, y, z);
+        }
+        assert!(constraints.rattle(&mut atoms, Time::fs(1.0)));
+        for bond in &constraints.bonds {
+            let r = (atoms[bond.i].pos - atoms[bond.j].pos).as_vec();
+            let v = (atoms[bond.i].vel - atoms[bond.j].vel).as_vec();
+            assert!(r.dot(v).abs() / r.dot(r).sqrt() < 1e-7, "rate {}", r.dot(v));
+        }
+    }
+
+    #[test]
+    fn rejects_linear_and_planar_groups() {
+        let line = test_atoms(&[
+            ("C", [0.0, 0.0, 0.0]),
+            ("C", [1.2, 0.0, 0.0]),
+            ("C", [2.4, 0.01, 0.0]),
+        ]);
+        let restrict = |atoms: &[Atom], group: Vec<usize>| {
+            Constraints::default().restrict(atoms, vec![], vec![], vec![group])
+        };
+        assert!(restrict(&line, vec![0, 1, 2]).is_err());
+        assert!(restrict(&line, vec![0, 1]).is_ok());
+        let square = test_atoms(&[
+            ("C", [0.0, 0.0, 0.0]),
+            ("C", [1.4, 0.0, 0.0]),
+            ("C", [1.4, 1.4, 0.02]),
+            ("C", [0.0, 1.4, 0.0]),
+        ]);
+        assert!(restrict(&square, vec![0, 1, 2, 3]).is_err());
+        assert!(restrict(&square, vec![0, 1, 2]).is_ok());
+        assert!(restrict(&tetrahedron(), vec![0, 1, 2, 3]).is_ok());
+    }
+
+    #[test]
+    fn counts_bonds_shared_with_rigid_groups() {
+        let atoms = tetrahedron();
+        //two bonds inside the group and two leaving it
+        let pairs = [(0, 1), (1, 2), (0, 4), (3, 4)];
+        let constraints = Constraints::new(&atoms, false, &pairs, 1e-6)
+            .unwrap()
+            .restrict(&atoms, vec![], vec![], vec![vec![0, 1, 2, 3]])
+            .unwrap();
+        //the group keeps 6 of its 12, the bonds leaving it take 2 more
+        assert_eq!(constraints.removed_dof(&atoms), 6 + 2);
+        let single = Constraints::new(&atoms, false, &pairs[2..], 1e-6).unwrap();
+        assert_eq!(single.removed_dof(&atoms), 2);
+    }
+}
@@ -0,0 +1,263 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::atom::Atom;
+use crate::trajectory::Snapshot;
+use crate::vectored::Vectored;
+
+//one AKMA time unit in fs, the unit of DELTA in a DCD header.
+const AKMA_FS: f64 = 48.88821;
+
+//byte offsets of NSET, NSAVC and NSTEP in the header.
+const NSET_OFFSET: u64 = 8;
+const NSAVC_OFFSET: u64 = 16;
+const NSTEP_OFFSET: u64 = 20;
+
+///Write the header of a CHARMM style DCD file. `time_step` is the
+///simulation time step in fs and `interval` the number of steps
+///between saved frames.
+pub fn write_header(path: &str, num_atoms: usize, time_step: f64, interval: usize) {
+    //NSET and NSTEP are updated as frames are appended
+    let mut icntrl = [0i32; 20];
+    icntrl[2] = interval as i32;
+    icntrl[19] = 24;
+
+    let mut buffer: Vec<u8> = vec![];
+    record(&mut buffer, |x| {
+        x.extend_from_slice(b"CORD");
+        for (index, value) in icntrl.iter().enumerate() {
+            match index {
+                9 => x.extend_from_slice(&((time_step / AKMA_FS) as f32).to_le_bytes()),
+                _ => x.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+    });
+    record(&mut buffer, |x| {
+        x.extend_from_slice(&1i32.to_le_bytes());
+        let mut title = format!("{:<80}", "Created by EZAIMD").into_bytes();
+        title.truncate(80);
+        x.extend_from_slice(&title);
+    });
+    record(&mut buffer, |x| {
+        x.extend_from_slice(&(num_atoms as i32).to_le_bytes())
+    });
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)
+        .expect("failed to init dcd trajectory");
+    file.write_all(&buffer).expect("failed to write dcd header");
+}
+
+///Append a frame to a DCD file and update the frame count in its header.
+pub fn write_frame(path: &str, atoms: &[Atom]) {
+    let mut buffer: Vec<u8> = vec![];
+    let coords: [fn(&Atom) -> f64; 3] = [
+        |x| x.pos.as_vec().x,
+        |x| x.pos.as_vec().y,
+        |x| x.pos.as_vec().z,
+    ];
+    for coord in coords {
+        record(&mut buffer, |x| {
+            for atom in atoms {
+                x.extend_from_slice(&(coord(atom) as f32).to_le_bytes());
+            }
+        });
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .expect("failed to report dcd trajectory");
+    file.seek(SeekFrom::End(0))
+        .expect("failed to seek in dcd trajectory");
+    file.write_all(&buffer).expect("failed to write dcd frame");
+
+    let nset = read_i32_at(&mut file, NSET_OFFSET).expect("corrupt dcd header") + 1;
+    let nsavc = read_i32_at(&mut file, NSAVC_OFFSET).expect("corrupt dcd header");
+    write_i32_at(&mut file, NSET_OFFSET, nset);
+    write_i32_at(&mut file, NSTEP_OFFSET, (nset - 1) * nsavc);
+}
+
+///Read every frame of a DCD file.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Snapshot>> {
+    let mut buffer: Vec<u8> = vec![];
+    File::open(path.as_ref())
+        .with_context(|| format!("failed to open {}", path.as_ref().display()))?
+        .read_to_end(&mut buffer)?;
+    let mut reader = Records::new(&buffer);
+
+    let header = reader.next_record()?;
+    if header.len() != 84 || &header[0..4] != b"CORD" {
+        bail!("not a little endian CORD dcd file");
+    }
+    let icntrl = (0..20)
+        .map(|x| i32_le(&header[4 + 4 * x..]))
+        .collect::<Vec<i32>>();
+    let delta = f32::from_le_bytes(header[40..44].try_into()?) as f64 * AKMA_FS;
+    let has_cell = icntrl[10] != 0;
+
+    reader.next_record()?;
+    let num_atoms = i32_le(reader.next_record()?) as usize;
+
+    let mut frames = vec![];
+    while !reader.is_empty() {
+        if has_cell {
+            reader.next_record()?;
+        }
+        let mut axes = vec![];
+        for _ in 0..3 {
+            let data = reader.next_record()?;
+            if data.len() != 4 * num_atoms {
+                bail!("dcd frame {} has the wrong number of atoms", frames.len());
+            }
+            axes.push(
+                data.chunks(4)
+                    .map(|x| f32::from_le_bytes(x.try_into().unwrap()) as f64)
+                    .collect::<Vec<f64>>(),
+            );
+        }
+        let step = (icntrl[1] + frames.len() as i32 * icntrl[2].max(1)) as usize;
+        frames.push(Snapshot {
+            step,
+            time: step as f64 * delta,
+            coords: (0..num_atoms)
+                .map(|x| [axes[0][x], axes[1][x], axes[2][x]])
+                .collect(),
+        });
+    }
+    Ok(frames)
+}
+
+//Fortran unformatted record: length, data, length.
+fn record<F: FnOnce(&mut Vec<u8>)>(buffer: &mut Vec<u8>, fill: F) {
+    let mut data = vec![];
+    fill(&mut data);
+    let length = (data.len() as i32).to_le_bytes();
+    buffer.extend_from_slice(&length);
+    buffer.extend_from_slice(&data);
+    buffer.extend_from_slice(&length);
+}
+
+fn i32_le(bytes: &[u8]) -> i32 {
+    i32::from_le_bytes(bytes[0..4].try_into().unwrap())
+}
+
+fn read_i32_at(file: &mut File, offset: u64) -> Result<i32> {
+    let mut bytes = [0u8; 4];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn write_i32_at(file: &mut File, offset: u64, value: i32) {
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(&value.to_le_bytes()))
+        .expect("failed to update dcd header");
+}
+
+struct Records<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    fn new(buffer: &'a [u8]) -> Records<'a> {
+        Records { buffer }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    fn next_record(&mut self) -> Result<&'a [u8]> {
+        if self.buffer.len() < 4 {
+            return Err(anyhow!("truncated dcd record"));
+        }
+        let length = i32_le(self.buffer) as usize;
+        if self.buffer.len() < length + 8 {
+            bail!("truncated dcd record");
+        }
+        let data = &self.buffer[4..4 + length];
+        if i32_le(&self.buffer[4 + length..]) as usize != length {
+            bail!("mismatched dcd record markers");
+        }
+        self.buffer = &self.buffer[length + 8..];
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::vectored::Displacement;
+
+    //byte layout of the CHARMM header: an 84 byte CORD record of NSET,
+    //ISTART, NSAVC, NSTEP, ..., DELTA as ICNTRL(10), the unit cell flag as
+    //ICNTRL(11) and the version as ICNTRL(20), one 80 character title and
+    //the number of atoms, each framed by its length.
+    #[test]
+    fn charmm_header() {
+        let atoms = test_atoms(&[("O", [0.0; 3]), ("H", [0.96, 0.0, 0.0])]);
+        let path = std::env::temp_dir().join(format!("ezaimd_header_{}.dcd", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        write_header(path_str, atoms.len(), 0.5, 10);
+        write_frame(path_str, &atoms);
+        write_frame(path_str, &atoms);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let int = |offset: usize| i32_le(&bytes[offset..]);
+        assert_eq!((int(0), &bytes[4..8], int(88)), (84, &b"CORD"[..], 84));
+        //NSET, ISTART, NSAVC and NSTEP
+        assert_eq!([int(8), int(12), int(16), int(20)], [2, 0, 10, 10]);
+        let delta = f32::from_le_bytes(bytes[44..48].try_into().unwrap()) as f64;
+        assert!((delta * AKMA_FS - 0.5).abs() < 1e-6);
+        assert_eq!((int(48), int(84)), (0, 24));
+        assert_eq!((int(92), int(96), int(180)), (84, 1, 84));
+        assert!(bytes[100..180].starts_with(b"Created by EZAIMD"));
+        assert_eq!((int(184), int(188), int(192)), (4, 2, 4));
+        //a frame is three records of single precision x, y and z
+        assert_eq!(bytes.len(), 196 + 2 * 3 * (8 + 4 * atoms.len()));
+        assert_eq!((int(196), int(208)), (8, 8));
+    }
+
+    #[test]
+    fn round_trip() {
+        let atoms = test_atoms(&[
+            ("O", [0.0, 0.0, 0.0]),
+            ("H", [0.96, 0.0, 0.0]),
+            ("H", [-0.24, 0.93, 0.0]),
+            ("C", [-12.5, 40.25, 3.125]),
+        ]);
+        let path = std::env::temp_dir().join(format!("ezaimd_{}.dcd", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        write_header(path_str, atoms.len(), 0.5, 10);
+        write_frame(path_str, &atoms);
+        let mut moved = atoms.clone();
+        for atom in moved.iter_mut() {
+            atom.pos = atom.pos + Displacement::new(1.5, -2.0, 0.25);
+        }
+        write_frame(path_str, &moved);
+        let frames = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].step - frames[0].step, 10);
+        assert!((frames[1].time - frames[1].step as f64 * 0.5).abs() < 1e-4);
+        for (frame, atoms) in frames.iter().zip([&atoms, &moved]) {
+            for (read, atom) in frame.coords.iter().zip(atoms.iter()) {
+                let pos = atom.pos.as_vec();
+                for (a, b) in read.iter().zip([pos.x, pos.y, pos.z]) {
+                    assert!((a - b).abs() < 1e-5, "{} read back as {}", b, a);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+
+use regex::Regex;
+use rgaussian16::Gaussian;
+
+use crate::atom::Atom;
+use crate::provider::{ForceOutput, ForceProvider};
+use crate::units;
+use crate::vectored::{AtomicForce, Force, Vectored};
+
+///Forces from Gaussian16, run with the route and resources of a
+///configuration file. Input and output are written to the given files.
+pub struct GaussianProvider<'a> {
+    config: &'a str,
+    input: &'a str,
+    output: &'a str,
+}
+
+impl<'a> GaussianProvider<'a> {
+    pub fn new(config: &'a str, input: &'a str, output: &'a str) -> GaussianProvider<'a> {
+        GaussianProvider {
+            config,
+            input,
+            output,
+        }
+    }
+
+    fn interface(&self) -> Gaussian {
+        let config =
+            File::open(self.config).unwrap_or_else(|_| panic!("failed to open {}", self.config));
+        Gaussian::new(config).unwrap_or_else(|_| {
+            panic!(
+                "failed to generate Gaussian16 interface. Check {}",
+                self.config
+            )
+        })
+    }
+
+    fn generate_input(&self, atoms: &[Atom]) {
+        let input = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(self.input)
+            .unwrap_or_else(|_| panic!("failed to spawn {} file", self.input));
+
+        let coords = Self::gen_coords(atoms);
+
+        self.interface()
+            .gen_input(&input)
+            .expect("failed to write input");
+        writeln!(&input, "\n{}\n", coords).expect("failed to write atomic coords");
+    }
+
+    fn gen_coords(atoms: &[Atom]) -> String {
+        atoms
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} {:.5} {:.5} {:.5}",
+                    x.symbol,
+                    x.pos.as_vec().x,
+                    x.pos.as_vec().y,
+                    x.pos.as_vec().z
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn run_gaussian(&self) {
+        let input = File::open(self.input)
+            .unwrap_or_else(|_| panic!("failed to open {} for Gaussian16 run", self.input));
+        let output = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(self.output)
+            .expect("failed to create output file");
+
+        self.interface()
+            .run(input, output)
+            .expect("Gaussian16 calculation failed");
+    }
+}
+
+impl ForceProvider for GaussianProvider<'_> {
+    fn compute(&self, atoms: &[Atom]) -> ForceOutput {
+        self.generate_input(atoms);
+        self.run_gaussian();
+        let output =
+            File::open(self.output).unwrap_or_else(|_| panic!("failed to open {}", self.output));
+        let data = GaussianOutput::new(output);
+        ForceOutput {
+            energy: data.scf * units::HARTREE_KJ_MOL,
+            restraint: 0.0,
+            wall: 0.0,
+            bias: 0.0,
+            cycles: data.cycles,
+            forces: data.forces,
+        }
+    }
+}
+
+struct GaussianOutput {
+    scf: f64,
+    cycles: Option<usize>,
+    forces: Vec<Force<f64>>,
+}
+
+impl GaussianOutput {
+    pub fn new(mut file: File) -> GaussianOutput {
+        let mut buffer = String::new();
+        let to_find = Regex::new(r"^(\s)+\d+(\s)+\d+((\s+)-?\d+.\d+){3}").unwrap();
+        let to_find_scf = Regex::new(r"^ SCF Done").unwrap();
+        let to_find_cycles = Regex::new(r"after\s+(\d+)\s+cycles").unwrap();
+        file.read_to_string(&mut buffer).unwrap();
+
+        let forces = buffer
+            .clone()
+            .lines()
+            .filter(|x| to_find.is_match(x))
+            .map(|x| x.to_string())
+            .map(Self::convert_to_force)
+            .collect::<Vec<Force<f64>>>();
+
+        let scf_line = buffer
+            .lines()
+            .filter(|x| to_find_scf.is_match(x))
+            .map(|x| x.to_string())
+            .rev()
+            .take(1)
+            .collect::<String>();
+
+        let scf = scf_line
+            .split_whitespace()
+            .find_map(|x| x.parse::<f64>().ok())
+            .unwrap();
+
+        let cycles = to_find_cycles
+            .captures(&scf_line)
+            .and_then(|x| x[1].parse::<usize>().ok());
+
+        GaussianOutput {
+            scf,
+            cycles,
+            forces,
+        }
+    }
+
+    fn convert_to_force(line: String) -> Force<f64> {
+        let result = line
+            .split_whitespace()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+
+        let force = AtomicForce::new(
+            result[2].parse::<f64>().unwrap(),
+            result[3].parse::<f64>().unwrap(),
+            result[4].parse::<f64>().unwrap(),
+        );
+
+        //convert from Eh/Bohr to amu A/fs^2
+        force.into()
+    }
+}
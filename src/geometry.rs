@@ -0,0 +1,258 @@
+use crate::vector::Vector3D;
+
+//below this sine an angle is taken as linear, where its gradient is undefined
+const LINEAR_SINE: f64 = 1e-8;
+
+type Vector = Vector3D<f64>;
+
+///Distance between two points in A and its gradient with respect to each.
+pub fn distance(a: Vector, b: Vector) -> (f64, [Vector; 2]) {
+    let r = a - b;
+    let d = r.dot(r).sqrt();
+    if d == 0.0 {
+        return (0.0, [zero(); 2]);
+    }
+    let unit = r / d;
+    (d, [unit, unit * -1.0])
+}
+
+///Angle a-b-c at `b` in radians and its gradient.
+pub fn angle(a: Vector, b: Vector, c: Vector) -> (f64, [Vector; 3]) {
+    let u = a - b;
+    let v = c - b;
+    let (lu, lv) = (u.dot(u).sqrt(), v.dot(v).sqrt());
+    if lu == 0.0 || lv == 0.0 {
+        return (0.0, [zero(); 3]);
+    }
+    let (uh, vh) = (u / lu, v / lv);
+    let cos = uh.dot(vh).clamp(-1.0, 1.0);
+    let theta = cos.acos();
+    let sin = theta.sin();
+    if sin < LINEAR_SINE {
+        return (theta, [zero(); 3]);
+    }
+    let ga = (uh * cos - vh) / (lu * sin);
+    let gc = (vh * cos - uh) / (lv * sin);
+    (theta, [ga, (ga + gc) * -1.0, gc])
+}
+
+///Dihedral a-b-c-d in radians within (-pi, pi] and its gradient,
+///Blondel and Karplus, J. Comput. Chem. 17, 1132 (1996).
+pub fn dihedral(a: Vector, b: Vector, c: Vector, d: Vector) -> (f64, [Vector; 4]) {
+    let f = a - b;
+    let g = b - c;
+    let h = d - c;
+    let m = f.cross(g);
+    let n = h.cross(g);
+    let (mm, nn) = (m.dot(m), n.dot(n));
+    let lg = g.dot(g).sqrt();
+    if mm == 0.0 || nn == 0.0 || lg == 0.0 {
+        return (0.0, [zero(); 4]);
+    }
+    let phi = (n.cross(m).dot(g) / lg).atan2(m.dot(n));
+    let ga = m * (-lg / mm);
+    let gd = n * (lg / nn);
+    let fg = f.dot(g) / (mm * lg);
+    let hg = h.dot(g) / (nn * lg);
+    let gb = m * (lg / mm + fg) - n * hg;
+    let gc = n * (hg - lg / nn) - m * fg;
+    (phi, [ga, gb, gc, gd])
+}
+
+fn zero() -> Vector {
+    Vector3D::new(0.0, 0.0, 0.0)
+}
+
+///Rotation taking the centred points `y` closest onto the centred points
+///`x` in the least squares sense, from the quaternion of Horn,
+///J. Opt. Soc. Am. A 4, 629 (1987).
+pub fn fit(x: &[Vector], y: &[Vector]) -> [[f64; 3]; 3] {
+    let mut s = [[0.0; 3]; 3];
+    for (p, q) in x.iter().zip(y) {
+        let (p, q) = ([p.x, p.y, p.z], [q.x, q.y, q.z]);
+        for (a, row) in s.iter_mut().enumerate() {
+            for (b, value) in row.iter_mut().enumerate() {
+                *value += q[a] * p[b];
+            }
+        }
+    }
+    let [[xx, xy, xz], [yx, yy, yz], [zx, zy, zz]] = s;
+    let n = [
+        [xx + yy + zz, yz - zy, zx - xz, xy - yx],
+        [yz - zy, xx - yy - zz, xy + yx, zx + xz],
+        [zx - xz, xy + yx, -xx + yy - zz, yz + zy],
+        [xy - yx, zx + xz, yz + zy, -xx - yy + zz],
+    ];
+    let [a, b, c, d] = largest_eigenvector(n);
+    [
+        [
+            a * a + b * b - c * c - d * d,
+            2.0 * (b * c - a * d),
+            2.0 * (b * d + a * c),
+        ],
+        [
+            2.0 * (b * c + a * d),
+            a * a - b * b + c * c - d * d,
+            2.0 * (c * d - a * b),
+        ],
+        [
+            2.0 * (b * d - a * c),
+            2.0 * (c * d + a * b),
+            a * a - b * b - c * c + d * d,
+        ],
+    ]
+}
+
+///`matrix` times `v`.
+pub fn rotate(matrix: &[[f64; 3]; 3], v: Vector) -> Vector {
+    let [x, y, z] = matrix.map(|row| row[0] * v.x + row[1] * v.y + row[2] * v.z);
+    Vector3D::new(x, y, z)
+}
+
+//eigenvector of the largest eigenvalue of a symmetric matrix by Jacobi rotations.
+fn largest_eigenvector(mut a: [[f64; 4]; 4]) -> [f64; 4] {
+    let mut v: [[f64; 4]; 4] =
+        std::array::from_fn(|i| std::array::from_fn(|j| (i == j) as u8 as f64));
+    for _ in 0..50 {
+        let off: f64 = (0..4)
+            .flat_map(|p| (p + 1..4).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum();
+        if off < 1e-30 {
+            break;
+        }
+        for p in 0..4 {
+            for q in p + 1..4 {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (x, y) = (row[p], row[q]);
+                    row[p] = c * x - s * y;
+                    row[q] = s * x + c * y;
+                }
+                let (x, y) = (a[p], a[q]);
+                a[p] = std::array::from_fn(|k| c * x[k] - s * y[k]);
+                a[q] = std::array::from_fn(|k| s * x[k] + c * y[k]);
+                for row in v.iter_mut() {
+                    let (x, y) = (row[p], row[q]);
+                    row[p] = c * x - s * y;
+                    row[q] = s * x + c * y;
+                }
+            }
+        }
+    }
+    let best = (0..4).fold(0, |best, i| if a[i][i] > a[best][best] { i } else { best });
+    v.map(|row| row[best])
+}
+
+///Compare the gradient returned by `f` with central differences at the
+///points, differences of periodic values wrapped into one period, for tests.
+#[cfg(test)]
+pub fn check_gradient(
+    f: impl Fn(&[Vector]) -> (f64, Vec<Vector>),
+    points: &[Vector],
+    period: Option<f64>,
+) {
+    let h = 1e-6;
+    let (_, gradient) = f(points);
+    for (index, g) in gradient.iter().enumerate() {
+        for (axis, analytic) in [g.x, g.y, g.z].into_iter().enumerate() {
+            let shifted = |sign: f64| {
+                let mut step = [0.0; 3];
+                step[axis] = sign * h;
+                let mut moved = points.to_vec();
+                moved[index] = moved[index] + Vector3D::new(step[0], step[1], step[2]);
+                f(&moved).0
+            };
+            let mut delta = shifted(1.0) - shifted(-1.0);
+            if let Some(period) = period {
+                delta -= period * (delta / period).round();
+            }
+            let numeric = delta / (2.0 * h);
+            assert!(
+                (numeric - analytic).abs() < 1e-5 * analytic.abs().max(1.0),
+                "point {} axis {}: analytic {} numeric {}",
+                index,
+                axis,
+                analytic,
+                numeric
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn v(x: f64, y: f64, z: f64) -> Vector {
+        Vector3D::new(x, y, z)
+    }
+
+    #[test]
+    fn distance_gradient() {
+        let f = |p: &[Vector]| {
+            let (value, g) = distance(p[0], p[1]);
+            (value, g.to_vec())
+        };
+        check_gradient(f, &[v(0.1, 0.2, -0.3), v(1.2, -0.4, 0.5)], None);
+    }
+
+    #[test]
+    fn angle_gradient() {
+        let f = |p: &[Vector]| {
+            let (value, g) = angle(p[0], p[1], p[2]);
+            (value, g.to_vec())
+        };
+        check_gradient(
+            f,
+            &[v(1.0, 0.1, 0.0), v(0.0, 0.0, 0.2), v(-0.3, 1.1, 0.4)],
+            None,
+        );
+        //a few hundredths of a degree from linear
+        let near = 179.97 * PI / 180.0;
+        let points = [
+            v(1.0, 0.0, 0.0),
+            v(0.0, 0.0, 0.0),
+            v(near.cos(), near.sin(), 0.0),
+        ];
+        assert!((angle(points[0], points[1], points[2]).0 - near).abs() < 1e-9);
+        check_gradient(f, &points, None);
+    }
+
+    #[test]
+    fn dihedral_gradient() {
+        let f = |p: &[Vector]| {
+            let (value, g) = dihedral(p[0], p[1], p[2], p[3]);
+            (value, g.to_vec())
+        };
+        let points = [
+            v(1.0, 0.8, 0.1),
+            v(0.0, 0.0, 0.0),
+            v(0.0, 0.0, 1.5),
+            v(-0.7, 0.9, 2.0),
+        ];
+        check_gradient(f, &points, Some(2.0 * PI));
+        //on either side of the branch point at +-180 degrees and on it
+        for degrees in [179.99, 180.0, -179.99] {
+            let phi: f64 = degrees * PI / 180.0;
+            let points = [
+                v(1.0, 0.0, -0.5),
+                v(0.0, 0.0, 0.0),
+                v(0.0, 0.0, 1.5),
+                v(phi.cos(), phi.sin(), 2.0),
+            ];
+            let value = dihedral(points[0], points[1], points[2], points[3]).0;
+            let wrapped = value - phi - 2.0 * PI * ((value - phi) / (2.0 * PI)).round();
+            assert!(wrapped.abs() < 1e-9, "{} for {}", value, degrees);
+            check_gradient(f, &points, Some(2.0 * PI));
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use clap::ArgEnum;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::quantity::Time;
+use crate::units;
+use crate::vectored::{Force, Vectored, Velocity};
+
+///Settings of the step being integrated.
+pub struct StepContext {
+    pub time_step: Time,
+    ///target temperature in K for stochastic integrators
+    pub temperature: f64,
+}
+
+///A scheme advancing the atoms over one step with a single force
+///evaluation in between `advance` and `finish`. Frozen atoms are left alone.
+pub trait Integrate {
+    ///move the atoms, `force` holds the forces at the current positions.
+    ///Returns the kinetic energy in kJ/mol handed to a heat bath.
+    fn advance(&mut self, atoms: &mut [Atom], step: &StepContext) -> f64;
+
+    ///complete the velocities once `next_force` holds the forces at the new positions.
+    fn finish(&mut self, atoms: &mut [Atom], step: &StepContext);
+}
+
+///Integrators selectable on the command line.
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegratorKind {
+    VelocityVerlet,
+    Leapfrog,
+    Beeman,
+    Baoab,
+}
+
+///The integrator of a simulation along with its state, stored in the checkpoint.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Integrator {
+    VelocityVerlet(VelocityVerlet),
+    Leapfrog(Leapfrog),
+    Beeman(Beeman),
+    Baoab(Baoab),
+}
+
+impl Default for Integrator {
+    fn default() -> Self {
+        Integrator::VelocityVerlet(VelocityVerlet {})
+    }
+}
+
+impl Integrator {
+    ///`friction` is the Langevin collision frequency in 1/ps.
+    pub fn new(kind: IntegratorKind, friction: f64) -> Integrator {
+        match kind {
+            IntegratorKind::VelocityVerlet => Integrator::VelocityVerlet(VelocityVerlet {}),
+            IntegratorKind::Leapfrog => Integrator::Leapfrog(Leapfrog::default()),
+            IntegratorKind::Beeman => Integrator::Beeman(Beeman::default()),
+            IntegratorKind::Baoab => Integrator::Baoab(Baoab { friction }),
+        }
+    }
+
+    ///true for integrators coupling the system to a heat bath themselves.
+    pub fn is_stochastic(&self) -> bool {
+        matches!(self, Integrator::Baoab(_))
+    }
+
+    ///forget the forces and velocities kept from earlier steps, which no
+    ///longer fit once the time step changes.
+    pub fn reset_history(&mut self) {
+        match self {
+            Integrator::Leapfrog(x) => *x = Leapfrog::default(),
+            Integrator::Beeman(x) => *x = Beeman::default(),
+            _ => {}
+        }
+    }
+
+    pub fn scheme(&mut self) -> &mut dyn Integrate {
+        match self {
+            Integrator::VelocityVerlet(x) => x,
+            Integrator::Leapfrog(x) => x,
+            Integrator::Beeman(x) => x,
+            Integrator::Baoab(x) => x,
+        }
+    }
+}
+
+///Velocity Verlet, Swope et al. J. Chem. Phys. 76, 637 (1982)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VelocityVerlet {}
+
+impl Integrate for VelocityVerlet {
+    fn advance(&mut self, atoms: &mut [Atom], step: &StepContext) -> f64 {
+        let dt = step.time_step;
+        for x in atoms.iter_mut().filter(|x| x.can_mv) {
+            x.pos = x.pos + x.vel * dt + 0.5 * (x.force / x.mass) * dt * dt;
+        }
+        0.0
+    }
+
+    fn finish(&mut self, atoms: &mut [Atom], step: &StepContext) {
+        let dt = step.time_step;
+        for x in atoms.iter_mut().filter(|x| x.can_mv) {
+            x.vel = x.vel + 0.5 * ((x.force + x.next_force) / x.mass) * dt;
+        }
+    }
+}
+
+///Leapfrog, the velocities are propagated at half steps,
+///v(t + dt/2) = v(t - dt/2) + a(t) dt, and the positions with them. The
+///on-step velocity is extrapolated with half of the last kick,
+///v(t + dt) = v(t + dt/2) + a(t) dt/2, without the new force, so it lags
+///velocity Verlet by (a(t + dt) - a(t)) dt/2 on identical positions. Changes made to the on-step velocities between
+///steps, by a thermostat for example, are added to the half step velocities.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Leapfrog {
+    //velocities half a step behind the positions
+    #[serde(default)]
+    half: Vec<Velocity<f64>>,
+    //on-step velocities as left by the last step
+    #[serde(default)]
+    on_step: Vec<Velocity<f64>>,
+}
+
+impl Integrate for Leapfrog {
+    fn advance(&mut self, atoms: &mut [Atom], step: &StepContext) -> f64 {
+        let dt = step.time_step;
+        if self.half.len() != atoms.len() || self.on_step.len() != atoms.len() {
+            self.half = atoms
+                .iter()
+                .map(|x| x.vel - 0.5 * (x.force / x.mass) * dt)
+                .collect();
+            self.on_step = atoms.iter().map(|x| x.vel).collect();
+        }
+        for ((x, half), on_step) in atoms.iter_mut().zip(&mut self.half).zip(&self.on_step) {
+            if !x.can_mv {
+                continue;
+            }
+            *half = *half + (x.vel - *on_step) + (x.force / x.mass) * dt;
+            x.pos = x.pos + *half * dt;
+        }
+        0.0
+    }
+
+    fn finish(&mut self, atoms: &mut [Atom], step: &StepContext) {
+        let dt = step.time_step;
+        for ((x, half), on_step) in atoms.iter_mut().zip(&self.half).zip(&mut self.on_step) {
+            if x.can_mv {
+                x.vel = *half + 0.5 * (x.force / x.mass) * dt;
+            }
+            *on_step = x.vel;
+        }
+    }
+}
+
+///Beeman, J. Comput. Phys. 20, 130 (1976)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Beeman {
+    //forces of the step before the current one
+    #[serde(default)]
+    previous: Vec<Force<f64>>,
+}
+
+impl Integrate for Beeman {
+    fn advance(&mut self, atoms: &mut [Atom], step: &StepContext) -> f64 {
+        let dt = step.time_step;
+        for (i, x) in atoms.iter_mut().enumerate() {
+            //without a history this is the velocity Verlet position update
+            let previous = self.previous.get(i).copied().unwrap_or(x.force);
+            if x.can_mv {
+                let acceleration = (4.0 * x.force - previous) / x.mass;
+                x.pos = x.pos + x.vel * dt + (1.0 / 6.0) * acceleration * dt * dt;
+            }
+        }
+        0.0
+    }
+
+    fn finish(&mut self, atoms: &mut [Atom], step: &StepContext) {
+        let dt = step.time_step;
+        //the first step is completed by velocity Verlet, as the error of
+        //a missing history would stay in the total energy
+        if self.previous.len() != atoms.len() {
+            VelocityVerlet {}.finish(atoms, step);
+        } else {
+            for (x, previous) in atoms.iter_mut().zip(self.previous.iter()) {
+                if x.can_mv {
+                    let acceleration = (2.0 * x.next_force + 5.0 * x.force - *previous) / x.mass;
+                    x.vel = x.vel + (1.0 / 6.0) * acceleration * dt;
+                }
+            }
+        }
+        self.previous = atoms.iter().map(|x| x.force).collect();
+    }
+}
+
+///BAOAB Langevin splitting, Leimkuhler and Matthews,
+///Appl. Math. Res. Express 2013, 34 (2013)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Baoab {
+    ///collision frequency in 1/ps
+    pub friction: f64,
+}
+
+impl Integrate for Baoab {
+    fn advance(&mut self, atoms: &mut [Atom], step: &StepContext) -> f64 {
+        let dt = step.time_step;
+        let half = Time::fs(0.5 * dt.as_fs());
+        let c1 = (-self.friction / 1000.0 * dt.as_fs()).exp();
+        let c2 = (1.0 - c1 * c1).sqrt();
+        let kt = units::kt(step.temperature) / units::MD_ENERGY_KJ_MOL;
+        let mut rng = rand::thread_rng();
+        let mut heat = 0.0;
+        for x in atoms.iter_mut().filter(|x| x.can_mv) {
+            x.vel = x.vel + (x.force / x.mass) * half;
+            x.pos = x.pos + x.vel * half;
+            let before = x.vel.sqr_norm();
+            let sigma = (kt / x.mass.as_amu()).sqrt();
+            let noise = Velocity::new(
+                StandardNormal.sample(&mut rng),
+                StandardNormal.sample(&mut rng),
+                StandardNormal.sample(&mut rng),
+            );
+            x.vel = c1 * x.vel + (c2 * sigma) * noise;
+            heat += 0.5 * x.mass.as_amu() * (before - x.vel.sqr_norm());
+            x.pos = x.pos + x.vel * half;
+        }
+        heat * units::MD_ENERGY_KJ_MOL
+    }
+
+    fn finish(&mut self, atoms: &mut [Atom], step: &StepContext) {
+        let dt = step.time_step;
+        for x in atoms.iter_mut().filter(|x| x.can_mv) {
+            x.vel = x.vel + 0.5 * (x.next_force / x.mass) * dt;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+
+    //a carbon atom on a spring to the origin, integrated for `steps` steps
+    fn oscillate(integrator: &mut dyn Integrate, steps: usize) -> Vec<Atom> {
+        let spring = |x: &Atom| -0.01 * x.pos.as_vec();
+        let mut atoms = test_atoms(&[("C", [1.0, 0.5, 0.0])]);
+        atoms[0].vel = Velocity::new(0.0, 0.01, 0.0);
+        let f = spring(&atoms[0]);
+        atoms[0].force = Force::new(f.x, f.y, f.z);
+        let step = StepContext {
+            time_step: Time::fs(1.0),
+            temperature: 0.0,
+        };
+        for _ in 0..steps {
+            integrator.advance(&mut atoms, &step);
+            for x in atoms.iter_mut() {
+                let f = spring(x);
+                x.next_force = Force::new(f.x, f.y, f.z);
+            }
+            integrator.finish(&mut atoms, &step);
+            for x in atoms.iter_mut() {
+                x.force = x.next_force;
+            }
+        }
+        atoms
+    }
+
+    #[test]
+    fn leapfrog_shares_positions_with_velocity_verlet() {
+        let verlet = oscillate(&mut VelocityVerlet {}, 50);
+        let leapfrog = oscillate(&mut Leapfrog::default(), 50);
+        let distance = (verlet[0].pos - leapfrog[0].pos).norm();
+        assert!(distance < 1e-10, "positions differ by {}", distance);
+        let difference = (verlet[0].vel - leapfrog[0].vel).norm();
+        assert!(
+            difference > 1e-6,
+            "velocities differ by only {}",
+            difference
+        );
+    }
+
+    #[test]
+    fn leapfrog_carries_velocity_changes() {
+        let mut leapfrog = Leapfrog::default();
+        let mut atoms = oscillate(&mut leapfrog, 10);
+        let before = leapfrog.half[0];
+        atoms[0].vel = 0.5 * atoms[0].vel;
+        let change = atoms[0].vel - leapfrog.on_step[0];
+        let step = StepContext {
+            time_step: Time::fs(1.0),
+            temperature: 0.0,
+        };
+        let kick = (atoms[0].force / atoms[0].mass) * step.time_step;
+        leapfrog.advance(&mut atoms, &step);
+        let expected = before + change + kick;
+        assert!((leapfrog.half[0] - expected).norm() < 1e-12);
+    }
+}
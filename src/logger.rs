@@ -0,0 +1,207 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::ArgEnum;
+use serde::Serialize;
+
+///How much the program reports to the terminal while running.
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+///Timing and energy information gathered over a single simulation step.
+#[derive(Debug, Clone, Serialize)]
+pub struct StepRecord {
+    pub step: usize,
+    pub num_steps: usize,
+    pub time: f64,
+    pub gaussian_wall: f64,
+    pub step_wall: f64,
+    pub scf_cycles: Option<usize>,
+    pub pot_energy: f64,
+    pub kin_energy: f64,
+    pub tot_energy: f64,
+    pub drift: f64,
+    pub energy_unit: &'static str,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a, T: Serialize> {
+    timestamp: String,
+    event: &'a str,
+    #[serde(flatten)]
+    data: T,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct StepLine<'a> {
+    #[serde(flatten)]
+    record: &'a StepRecord,
+    eta: Option<f64>,
+}
+
+///Terminal and JSON lines logger. The JSON log, if requested, is
+///appended to on every write so that dashboards can follow it live.
+#[derive(Debug, Clone, Default)]
+pub struct Logger {
+    verbosity: Verbosity,
+    json: Option<String>,
+    started: Option<Instant>,
+    steps_done: usize,
+}
+
+impl Logger {
+    pub fn new(verbosity: Verbosity, json: Option<String>) -> Logger {
+        Logger {
+            verbosity,
+            json,
+            started: None,
+            steps_done: 0,
+        }
+    }
+
+    ///message shown at the normal and verbose levels.
+    pub fn info(&self, message: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("[{}] {}", timestamp(), message);
+        }
+        self.write_json("info", Message { message });
+    }
+
+    ///message shown at every level, written to stderr.
+    pub fn warn(&self, message: &str) {
+        eprintln!("[{}] WARNING {}", timestamp(), message);
+        self.write_json("warning", Message { message });
+    }
+
+    ///message shown only at the verbose level.
+    pub fn debug(&self, message: &str) {
+        if self.verbosity >= Verbosity::Verbose {
+            println!("[{}] {}", timestamp(), message);
+        }
+    }
+
+    ///start the clock used for the estimated time to completion.
+    pub fn start(&mut self) {
+        self.started = Some(Instant::now());
+        self.steps_done = 0;
+    }
+
+    pub fn step(&mut self, record: &StepRecord) {
+        self.steps_done += 1;
+        let eta = self.eta(record.num_steps.saturating_sub(record.step));
+
+        if self.verbosity >= Verbosity::Normal {
+            println!(
+                "[{}] step {}/{} time {:.2} fs | g16 {:.1} s | SCF {} | drift {:+.6} {} | ETA {}",
+                timestamp(),
+                record.step,
+                record.num_steps,
+                record.time,
+                record.gaussian_wall,
+                record
+                    .scf_cycles
+                    .map_or_else(|| "-".to_string(), |x| x.to_string()),
+                record.drift,
+                record.energy_unit,
+                eta.map_or_else(|| "-".to_string(), format_duration),
+            );
+        }
+        if self.verbosity >= Verbosity::Verbose {
+            println!(
+                "    step wall {:.1} s | potential {:.6} | kinetic {:.6} | total {:.6} {}",
+                record.step_wall,
+                record.pot_energy,
+                record.kin_energy,
+                record.tot_energy,
+                record.energy_unit
+            );
+        }
+
+        let eta = eta.map(|x| x.as_secs_f64());
+        self.write_json("step", StepLine { record, eta });
+    }
+
+    fn eta(&self, remaining: usize) -> Option<Duration> {
+        let started = self.started?;
+        if self.steps_done == 0 {
+            return None;
+        }
+        let per_step = started.elapsed().as_secs_f64() / self.steps_done as f64;
+        Some(Duration::from_secs_f64(per_step * remaining as f64))
+    }
+
+    fn write_json<T: Serialize>(&self, event: &str, data: T) {
+        let path = match &self.json {
+            Some(path) => path,
+            None => return,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("failed to open json log file");
+        let line = JsonLine {
+            timestamp: timestamp(),
+            event,
+            data,
+        };
+        let report = serde_json::to_string(&line).expect("unable to convert log line into string");
+        writeln!(file, "{}", report).expect("failed to write to json log file");
+    }
+}
+
+///current UTC time in ISO 8601 format.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+//convert days since 1970-01-01 into a (year, month, day) date.
+//see Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let clock = format!("{:02}:{:02}:{:02}", rem / 3600, (rem % 3600) / 60, rem % 60);
+    match days {
+        0 => clock,
+        _ => format!("{}d {}", days, clock),
+    }
+}
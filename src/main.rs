@@ -1,11 +1,41 @@
 #![allow(non_snake_case)]
 
 //program modules
+mod adaptive;
 mod atom;
 mod cli;
+mod colvar;
+mod constraint;
+mod dcd;
+mod gaussian;
+mod geometry;
+mod integrator;
+mod logger;
+mod metadynamics;
+mod molden;
+mod momentum;
+mod pdb;
+mod plumed;
+mod protocol;
+mod provider;
+mod quantity;
+mod report;
+mod respa;
+mod restraint;
+mod schedule;
+mod selection;
 mod simulation;
+mod steering;
+mod thermostat;
+mod tools;
+mod trajectory;
+mod umbrella;
+mod units;
 mod vector;
 mod vectored;
+mod wall;
+mod watchdog;
+mod xtc;
 
 //external module
 use anyhow::Result;
@@ -17,15 +47,34 @@ use cli::Args;
 
 fn main() -> Result<()> {
     //parse command line arguments
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    //stages and windows run in their own directories, keep the log in place
+    if let Some(path) = &args.log_file {
+        let path = std::env::current_dir()?.join(path);
+        args.log_file = Some(path.to_string_lossy().into_owned());
+    }
+
+    //analysis tools do not start a simulation
+    if let Some(command) = &args.command {
+        return tools::run(command);
+    }
+
+    //staged runs drive the simulation themselves
+    if let Some(path) = &args.protocol {
+        return protocol::run(path, &args);
+    }
+    if args.umbrella.is_some() {
+        return umbrella::run(&args);
+    }
 
     //init a new simulation or restart using the save.json state.
     let simulation = match args.restart {
-        true => Simulation::from_save(),
+        true => Simulation::from_save(&args)?,
         false => Simulation::new(&args)?.init_forces(),
     };
 
-    simulation.run();
+    simulation.run()?;
 
     Ok(())
 }
@@ -0,0 +1,382 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::cli::Args;
+use crate::colvar::Colvar;
+use crate::plumed::{self, Header};
+use crate::report;
+use crate::units::{self, EnergyUnit};
+use crate::vector::Vector3D;
+use crate::vectored::{Force, Vectored};
+
+//hills further than this many widths away are left out of the free energy grid
+const HILL_RANGE: f64 = 3.0;
+
+///A Gaussian hill of the bias, centred at values of the collective
+///variables in A or rad.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Hill {
+    ///time of deposition in fs
+    pub time: f64,
+    pub centre: Vec<f64>,
+    ///height in kJ/mol
+    pub height: f64,
+}
+
+///Well-tempered metadynamics, Barducci et al. Phys. Rev. Lett. 100, 020603
+///(2008). Every `pace` steps a hill is deposited on one or two collective
+///variables, its height scaled by exp(-V(s) / k (bias_factor - 1) T).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Metadynamics {
+    colvars: Vec<Colvar>,
+    ///hill widths in the internal units of the variables
+    widths: Vec<f64>,
+    ///initial hill height in kJ/mol
+    height: f64,
+    bias_factor: f64,
+    ///temperature of the system in K
+    temperature: f64,
+    pace: usize,
+    //rebuilt from the HILLS file on restart
+    #[serde(skip)]
+    hills: Vec<Hill>,
+}
+
+impl Metadynamics {
+    ///None unless variables are biased with `--metad`, widths are given
+    ///in the units of colvar.txt.
+    pub fn new(args: &Args, colvars: &[Colvar]) -> Result<Option<Metadynamics>> {
+        if args.metad.is_empty() {
+            return Ok(None);
+        }
+        if args.metad.len() > 2 {
+            bail!("metadynamics takes one or two collective variables");
+        }
+        if args.metad_width.len() != args.metad.len() {
+            bail!("--metad-width needs one width per biased variable");
+        }
+        let colvars = args
+            .metad
+            .iter()
+            .map(|name| {
+                colvars
+                    .iter()
+                    .find(|x| x.name == *name)
+                    .cloned()
+                    .with_context(|| format!("unknown collective variable \"{}\"", name))
+            })
+            .collect::<Result<Vec<Colvar>>>()?;
+        let height = args
+            .metad_height
+            .context("--metad needs a --metad-height")?;
+        if height <= 0.0 || args.metad_width.iter().any(|x| *x <= 0.0) {
+            bail!("the hill height and widths must be positive");
+        }
+        if args.bias_factor <= 1.0 || args.metad_temperature <= 0.0 || args.metad_pace == 0 {
+            bail!("metadynamics needs a bias factor above 1, a temperature and a pace");
+        }
+        let widths = colvars
+            .iter()
+            .zip(&args.metad_width)
+            .map(|(colvar, width)| width / colvar.display().0)
+            .collect();
+        Ok(Some(Metadynamics {
+            colvars,
+            widths,
+            height: height / args.energy_unit.convert(1.0),
+            bias_factor: args.bias_factor,
+            temperature: args.metad_temperature,
+            pace: args.metad_pace,
+            hills: vec![],
+        }))
+    }
+
+    ///add the bias forces in amu A/fs^2 to `forces`, returning the bias
+    ///energy in kJ/mol.
+    pub fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        let evaluated: Vec<(f64, Vec<Vector3D<f64>>)> =
+            self.colvars.iter().map(|x| x.evaluate(atoms)).collect();
+        let values: Vec<f64> = evaluated.iter().map(|x| x.0).collect();
+        let (energy, slopes) = self.bias(&values);
+        for ((_, gradient), slope) in evaluated.iter().zip(slopes) {
+            for (force, g) in forces.iter_mut().zip(gradient) {
+                let f = *g * (-slope / units::MD_ENERGY_KJ_MOL);
+                *force = *force + Force::new(f.x, f.y, f.z);
+            }
+        }
+        energy
+    }
+
+    ///whether a hill is due at `step`.
+    pub fn due(&self, step: usize) -> bool {
+        step.is_multiple_of(self.pace)
+    }
+
+    ///deposit a hill at the current values of the variables, returning it.
+    pub fn deposit(&mut self, atoms: &[Atom], time: f64) -> Hill {
+        let centre: Vec<f64> = self.colvars.iter().map(|x| x.evaluate(atoms).0).collect();
+        let (bias, _) = self.bias(&centre);
+        let delta_t = (self.bias_factor - 1.0) * self.temperature;
+        let height = self.height * (-bias / units::kt(delta_t)).exp();
+        let hill = Hill {
+            time,
+            centre,
+            height,
+        };
+        self.hills.push(hill.clone());
+        hill
+    }
+
+    ///header of the HILLS file, centres and widths in the units of colvar.txt.
+    pub fn hills_header(&self, unit: EnergyUnit) -> String {
+        let mut fields = vec!["time".to_string()];
+        fields.extend(self.colvars.iter().map(|x| x.name.clone()));
+        fields.extend(self.colvars.iter().map(|x| format!("sigma_{}", x.name)));
+        fields.extend(["height".to_string(), "biasf".to_string()]);
+        let mut header = Header::new(fields).set("energy_unit", unit.label());
+        for colvar in self.colvars.iter().filter(|x| x.periodic()) {
+            header = header.periodic(&colvar.name);
+        }
+        header.write()
+    }
+
+    ///HILLS file holding the hills deposited so far, as carried into a stage.
+    pub fn hills_file(&self, unit: EnergyUnit) -> String {
+        let hills = self.hills.iter().map(|x| self.hills_line(x, unit));
+        self.hills_header(unit) + &hills.collect::<String>()
+    }
+
+    ///restore the hills deposited up to `until` in fs from a HILLS file
+    ///written in `unit`.
+    pub fn load_hills(&mut self, path: &str, unit: EnergyUnit, until: f64) -> Result<()> {
+        let (header, rows) = plumed::read(path)?;
+        let count = self.colvars.len();
+        if header.fields.len() != 2 * count + 3 {
+            bail!("the FIELDS of {} do not match the biased variables", path);
+        }
+        self.hills.clear();
+        for row in rows {
+            if row[0] > until {
+                break;
+            }
+            let centre = self
+                .colvars
+                .iter()
+                .zip(&row[1..])
+                .map(|(colvar, x)| x / colvar.display().0)
+                .collect();
+            self.hills.push(Hill {
+                time: row[0],
+                centre,
+                height: row[2 * count + 1] / unit.convert(1.0),
+            });
+        }
+        Ok(())
+    }
+
+    ///line of the HILLS file for a hill.
+    pub fn hills_line(&self, hill: &Hill, unit: EnergyUnit) -> String {
+        let mut fields = vec![format!("{:.2}", hill.time)];
+        for (colvar, centre) in self.colvars.iter().zip(&hill.centre) {
+            fields.push(format!("{:.6}", centre * colvar.display().0));
+        }
+        for (colvar, width) in self.colvars.iter().zip(&self.widths) {
+            fields.push(format!("{:.6}", width * colvar.display().0));
+        }
+        fields.push(format!("{:.8}", unit.convert(hill.height)));
+        fields.push(format!("{}", self.bias_factor));
+        fields.join(" ") + "\n"
+    }
+
+    //bias in kJ/mol at the values and its derivative along each variable.
+    fn bias(&self, values: &[f64]) -> (f64, Vec<f64>) {
+        let mut energy = 0.0;
+        let mut slopes = vec![0.0; values.len()];
+        for hill in &self.hills {
+            let deltas: Vec<f64> = self
+                .colvars
+                .iter()
+                .zip(values.iter().zip(&hill.centre))
+                .zip(&self.widths)
+                .map(|((colvar, (value, centre)), width)| {
+                    colvar.difference(*value, *centre) / width
+                })
+                .collect();
+            let exponent: f64 = deltas.iter().map(|x| 0.5 * x * x).sum();
+            let gaussian = hill.height * (-exponent).exp();
+            energy += gaussian;
+            for ((slope, delta), width) in slopes.iter_mut().zip(&deltas).zip(&self.widths) {
+                *slope -= gaussian * delta / width;
+            }
+        }
+        (energy, slopes)
+    }
+}
+
+//a variable of a HILLS file as read back for the free energy.
+struct Axis {
+    name: String,
+    //range of a periodic variable
+    period: Option<(f64, f64)>,
+}
+
+///Rebuild the free energy surface from a HILLS file on a grid of `bins`
+///points per variable, F(s) = -V(s) bias_factor / (bias_factor - 1),
+///shifted to a minimum of 0.
+pub fn fes(path: &str, bins: usize, output: &str) -> Result<()> {
+    if bins < 2 {
+        bail!("the free energy needs at least two bins per variable");
+    }
+    let (header, rows) = plumed::read(path)?;
+    let fields = &header.fields;
+    let count = match fields.len() {
+        5 => 1,
+        7 => 2,
+        _ => bail!("{} has no FIELDS header of one or two variables", path),
+    };
+    if rows.is_empty() {
+        bail!("{} holds no hills", path);
+    }
+    let axes = (1..=count)
+        .map(|d| {
+            let name = fields[d].clone();
+            let period = header.period(&name, path)?;
+            Ok(Axis { name, period })
+        })
+        .collect::<Result<Vec<Axis>>>()?;
+    let unit = header.get("energy_unit").unwrap_or_default();
+    let bias_factor = rows[0][2 * count + 2];
+
+    //grid along each variable, periodic ones without the repeated end point
+    let grids: Vec<Vec<f64>> = axes
+        .iter()
+        .enumerate()
+        .map(|(d, axis)| {
+            let (low, high, points) = match axis.period {
+                Some((min, max)) => (min, max, bins + 1),
+                None => {
+                    let range = |f: fn(f64, f64) -> f64, sign: f64| {
+                        rows.iter()
+                            .map(|x| x[1 + d] + sign * HILL_RANGE * x[1 + count + d])
+                            .reduce(f)
+                            .unwrap()
+                    };
+                    (range(f64::min, -1.0), range(f64::max, 1.0), bins)
+                }
+            };
+            let step = (high - low) / (points - 1) as f64;
+            (0..bins).map(|i| low + i as f64 * step).collect()
+        })
+        .collect();
+    let bias = |point: &[f64]| -> f64 {
+        rows.iter()
+            .map(|row| {
+                let exponent: f64 = axes
+                    .iter()
+                    .enumerate()
+                    .map(|(d, axis)| {
+                        let mut delta = point[d] - row[1 + d];
+                        if let Some((min, max)) = axis.period {
+                            let period = max - min;
+                            delta -= period * (delta / period).round();
+                        }
+                        let scaled = delta / row[1 + count + d];
+                        0.5 * scaled * scaled
+                    })
+                    .sum();
+                row[2 * count + 1] * (-exponent).exp()
+            })
+            .sum()
+    };
+    let points: Vec<Vec<f64>> = match count {
+        1 => grids[0].iter().map(|x| vec![*x]).collect(),
+        _ => grids[0]
+            .iter()
+            .flat_map(|x| grids[1].iter().map(move |y| vec![*x, *y]))
+            .collect(),
+    };
+    let scale = -bias_factor / (bias_factor - 1.0);
+    let energies: Vec<f64> = points.iter().map(|x| scale * bias(x)).collect();
+    let minimum = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(output)
+        .with_context(|| format!("failed to open {}", output))?;
+    let mut columns: Vec<String> = axes.iter().map(|x| x.name.clone()).collect();
+    columns.push(format!("Free energy {}", unit).trim_end().to_string());
+    let mut to_write = report::columns(&columns);
+    for (index, (point, energy)) in points.iter().zip(&energies).enumerate() {
+        //blank lines between the rows of a surface, as gnuplot reads them
+        if count == 2 && index > 0 && index % bins == 0 {
+            to_write.push('\n');
+        }
+        let values: Vec<String> = point.iter().map(|x| format!("{:<30.6}", x)).collect();
+        to_write.push_str(&format!("{} {:.6}\n", values.join(" "), energy - minimum));
+    }
+    file.write_all(to_write.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fes_of_known_hills() {
+        let dir = std::env::temp_dir().join(format!("ezaimd_fes_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (hills, output) = (dir.join("HILLS"), dir.join("fes.txt"));
+        let (hills, output) = (hills.to_str().unwrap(), output.to_str().unwrap());
+        let centres = [(0.0, 1.0), (0.5, 0.6), (1.5, 0.8)];
+        let (sigma, bias_factor) = (0.3, 8.0);
+        let mut contents = "#! FIELDS time d sigma_d height biasf\n".to_string();
+        contents.push_str("#! SET energy_unit kJ/mol\n");
+        for (time, (centre, height)) in centres.iter().enumerate() {
+            contents.push_str(&format!(
+                "{} {} {} {} {}\n",
+                time, centre, sigma, height, bias_factor
+            ));
+        }
+        std::fs::write(hills, contents).unwrap();
+        fes(hills, 50, output).unwrap();
+
+        let expected = |x: f64| {
+            let bias: f64 = centres
+                .iter()
+                .map(|(c, h)| h * (-0.5 * ((x - c) / sigma).powi(2)).exp())
+                .sum();
+            -bias * bias_factor / (bias_factor - 1.0)
+        };
+        let rows: Vec<(f64, f64)> = std::fs::read_to_string(output)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|x| {
+                let values: Vec<f64> = x.split_whitespace().map(|x| x.parse().unwrap()).collect();
+                (values[0], values[1])
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rows.len(), 50);
+        assert_eq!(rows[0].0, -0.9);
+        let minimum = rows
+            .iter()
+            .map(|x| expected(x.0))
+            .fold(f64::INFINITY, f64::min);
+        for (x, energy) in rows {
+            assert!(
+                (energy - (expected(x) - minimum)).abs() < 1e-5,
+                "{} at {}",
+                energy,
+                x
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use crate::trajectory::Frame;
+use crate::units::EnergyUnit;
+use crate::vectored::Vectored;
+
+const GEOMETRIES: &str = "[GEOMETRIES] XYZ\n";
+const GEOCONV: &str = "[GEOCONV]\nenergy\n";
+
+///Create an empty Molden geometry animation file.
+pub fn init(path: &str) {
+    let to_write = format!("[Molden Format]\n{}", GEOMETRIES);
+    fs::write(path, to_write).expect("failed to init molden file");
+}
+
+///Append a frame to the geometries of a Molden file, its energy in
+///Hartree on the comment line. The energy section is added by `finish`.
+pub fn write_frame(path: &str, frame: &Frame) {
+    //Molden expects energies in Hartree
+    let energy = EnergyUnit::Hartree.convert(frame.pot_energy);
+    let coords = frame
+        .atoms
+        .iter()
+        .map(|x| {
+            let pos = x.pos.as_vec();
+            format!(
+                "{:<2} {:>14.8} {:>14.8} {:>14.8}\n",
+                x.symbol, pos.x, pos.y, pos.z
+            )
+        })
+        .collect::<String>();
+    let to_write = format!(
+        "{}\n step {} energy {:.10}\n{}",
+        frame.atoms.len(),
+        frame.step,
+        energy,
+        coords
+    );
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .expect("failed to report molden trajectory");
+    file.write_all(to_write.as_bytes())
+        .expect("failed to write molden frame");
+}
+
+///Close a Molden file with the [GEOCONV] section, the energies read back
+///from the comment lines of the geometries.
+pub fn finish(path: &str) {
+    let current = fs::read_to_string(path).expect("failed to read molden file");
+    let energies = current
+        .lines()
+        .filter(|x| x.trim_start().starts_with("step "))
+        .filter_map(|x| x.split_whitespace().last())
+        .map(|x| format!("{}\n", x))
+        .collect::<String>();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .expect("failed to finish molden file");
+    file.write_all(format!("{}{}", GEOCONV, energies).as_bytes())
+        .expect("failed to write molden energies");
+}
+
+///Drop the [GEOCONV] section of a finished Molden file so a restarted
+///run can append geometries again.
+pub fn reopen(path: &str) {
+    let current = fs::read_to_string(path).expect("failed to read molden file");
+    if let Some(split) = current.find(GEOCONV) {
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|x| x.set_len(split as u64))
+            .expect("failed to reopen molden file");
+    }
+}
@@ -0,0 +1,178 @@
+use crate::atom::Atom;
+use crate::vector::Vector3D;
+use crate::vectored::{Vectored, Velocity};
+
+//relative tolerance below which the atoms are taken as collinear
+//and the inertia tensor as singular
+const INERTIA_TOLERANCE: f64 = 1e-8;
+
+///Remove the centre of mass velocity and the rigid body rotation
+///about the centre of mass from the atoms.
+pub fn remove(atoms: &mut [Atom]) {
+    let total_mass: f64 = atoms.iter().map(|x| x.mass.as_amu()).sum();
+    if atoms.len() < 2 || total_mass == 0.0 {
+        return;
+    }
+    let zero = Vector3D::new(0.0, 0.0, 0.0);
+    let centre = atoms
+        .iter()
+        .fold(zero, |a, x| a + x.mass.as_amu() * x.pos.as_vec())
+        / total_mass;
+    let com_vel = atoms
+        .iter()
+        .fold(zero, |a, x| a + x.mass.as_amu() * x.vel.as_vec())
+        / total_mass;
+
+    let mut momentum = zero;
+    let mut inertia = [[0.0; 3]; 3];
+    for atom in atoms.iter() {
+        let r = atom.pos.as_vec() - centre;
+        let v = atom.vel.as_vec() - com_vel;
+        let mass = atom.mass.as_amu();
+        momentum = momentum + mass * r.cross(v);
+        let r = [r.x, r.y, r.z];
+        let sqr = r[0] * r[0] + r[1] * r[1] + r[2] * r[2];
+        for (i, row) in inertia.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                let delta = if i == j { sqr } else { 0.0 };
+                *value += mass * (delta - r[i] * r[j]);
+            }
+        }
+    }
+    let omega = angular_velocity(&inertia, momentum);
+
+    for atom in atoms.iter_mut() {
+        let r = atom.pos.as_vec() - centre;
+        let vec = atom.vel.as_vec() - com_vel - omega.cross(r);
+        atom.vel = Velocity::new(vec.x, vec.y, vec.z);
+    }
+}
+
+///Number of rotational degrees of freedom: none for a single atom,
+///two for a linear molecule and three otherwise.
+pub fn rotational_dof(atoms: &[Atom]) -> usize {
+    if atoms.len() < 2 {
+        return 0;
+    }
+    let first = atoms[0].pos.as_vec();
+    let axis =
+        atoms
+            .iter()
+            .map(|x| x.pos.as_vec() - first)
+            .fold(Vector3D::new(0.0, 0.0, 0.0), |a, x| {
+                if x.dot(x) > a.dot(a) {
+                    x
+                } else {
+                    a
+                }
+            });
+    let length = axis.dot(axis);
+    let linear = atoms.iter().all(|x| {
+        let r = x.pos.as_vec() - first;
+        let off_axis = r.cross(axis);
+        off_axis.dot(off_axis) <= INERTIA_TOLERANCE * length * length
+    });
+    match linear {
+        true => 2,
+        false => 3,
+    }
+}
+
+//solve I w = L. A linear molecule has no inertia about its axis and
+//L is perpendicular to it, so w = L / I with I the other moment.
+fn angular_velocity(inertia: &[[f64; 3]; 3], momentum: Vector3D<f64>) -> Vector3D<f64> {
+    let m = inertia;
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    if trace == 0.0 {
+        return Vector3D::new(0.0, 0.0, 0.0);
+    }
+    if det.abs() <= INERTIA_TOLERANCE * trace.powi(3) {
+        return momentum / (trace / 2.0);
+    }
+    let l = [momentum.x, momentum.y, momentum.z];
+    //Cramer's rule, replacing column `k` with L
+    let solve = |k: usize| {
+        let c = |i: usize, j: usize| if j == k { l[i] } else { m[i][j] };
+        (c(0, 0) * (c(1, 1) * c(2, 2) - c(1, 2) * c(2, 1))
+            - c(0, 1) * (c(1, 0) * c(2, 2) - c(1, 2) * c(2, 0))
+            + c(0, 2) * (c(1, 0) * c(2, 1) - c(1, 1) * c(2, 0)))
+            / det
+    };
+    Vector3D::new(solve(0), solve(1), solve(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::vectored::Position;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn kinetic(atoms: &[Atom]) -> f64 {
+        atoms
+            .iter()
+            .map(|x| 0.5 * x.mass.as_amu() * x.vel.as_vec().dot(x.vel.as_vec()))
+            .sum()
+    }
+
+    //linear and angular momentum about the centre of mass
+    fn momenta(atoms: &[Atom]) -> (Vector3D<f64>, Vector3D<f64>) {
+        let zero = Vector3D::new(0.0, 0.0, 0.0);
+        let total_mass: f64 = atoms.iter().map(|x| x.mass.as_amu()).sum();
+        let centre = atoms
+            .iter()
+            .fold(zero, |a, x| a + x.mass.as_amu() * x.pos.as_vec())
+            / total_mass;
+        atoms.iter().fold((zero, zero), |(p, l), x| {
+            let mv = x.mass.as_amu() * x.vel.as_vec();
+            (p + mv, l + (x.pos.as_vec() - centre).cross(mv))
+        })
+    }
+
+    #[test]
+    fn removes_momenta_of_random_cluster() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let elements = ["C", "H", "C", "H", "H", "C", "H", "C"];
+        let mut atoms = test_atoms(&elements.map(|x| (x, [0.0; 3])));
+        for atom in atoms.iter_mut() {
+            let [x, y, z]: [f64; 3] = std::array::from_fn(|_| rng.gen_range(-3.0..3.0));
+            atom.pos = Position::new(x, y, z);
+            let [x, y, z]: [f64; 3] = std::array::from_fn(|_| rng.gen_range(-0.02..0.02));
+            atom.vel = Velocity::new(x, y, z);
+        }
+        let (p, l) = momenta(&atoms);
+        assert!(p.dot(p).sqrt() > 1e-3 && l.dot(l).sqrt() > 1e-3);
+
+        let mut moved = atoms.clone();
+        remove(&mut moved);
+        let (p, l) = momenta(&moved);
+        assert!(p.dot(p).sqrt() < 1e-12, "P = {:?}", p);
+        assert!(l.dot(l).sqrt() < 1e-12, "L = {:?}", l);
+
+        //the velocities taken out are a rigid motion, keeping every
+        //distance, and the kinetic energy accounted to the bath is its own
+        let removed: Vec<Vector3D<f64>> = atoms
+            .iter()
+            .zip(&moved)
+            .map(|(a, b)| a.vel.as_vec() - b.vel.as_vec())
+            .collect();
+        for i in 0..atoms.len() {
+            for j in 0..i {
+                let r = atoms[i].pos.as_vec() - atoms[j].pos.as_vec();
+                assert!((removed[i] - removed[j]).dot(r).abs() < 1e-14);
+            }
+        }
+        let rigid: f64 = atoms
+            .iter()
+            .zip(&removed)
+            .map(|(x, u)| 0.5 * x.mass.as_amu() * u.dot(*u))
+            .sum();
+        let correction = kinetic(&atoms) - kinetic(&moved);
+        assert!(rigid > 0.0);
+        assert!((correction - rigid).abs() < 1e-12 * kinetic(&atoms));
+    }
+}
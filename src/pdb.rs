@@ -0,0 +1,130 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::atom::{perceive_bonds, Atom};
+use crate::trajectory::Frame;
+use crate::vectored::Vectored;
+
+///Write a single model PDB file used as the topology of binary trajectories.
+pub fn write_topology(path: &str, atoms: &[Atom]) {
+    let mut to_write = atom_records(atoms);
+    to_write.push("END".to_string());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)
+        .expect("failed to init topology file");
+    file.write_all((to_write.join("\n") + "\n").as_bytes())
+        .expect("failed to write topology");
+}
+
+///Append a frame as a MODEL to a multi-model PDB file. Connectivity is
+///perceived for every frame and written as CONECT records within the model.
+pub fn write_model(path: &str, frame: &Frame) {
+    let mut to_write = vec![
+        //model serial numbers start at 1
+        format!("MODEL     {:>4}", frame.index + 1),
+        format!(
+            "REMARK   1 STEP {} TIME {:.4} FS POTENTIAL {:.8} TOTAL {:.8} {}",
+            frame.step,
+            frame.time,
+            frame.unit.convert(frame.pot_energy),
+            frame.unit.convert(frame.tot_energy),
+            frame.unit.label()
+        ),
+    ];
+    to_write.extend(atom_records(frame.atoms));
+    to_write.extend(conect_records(frame.atoms));
+    to_write.push("ENDMDL".to_string());
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .expect("failed to report pdb trajectory");
+    file.write_all((to_write.join("\n") + "\n").as_bytes())
+        .expect("failed to write pdb model");
+}
+
+///Read the element symbols of every atom in a PDB file, in order.
+///Only the first model is read.
+pub fn read_symbols<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let file = File::open(path.as_ref())
+        .with_context(|| format!("failed to open {}", path.as_ref().display()))?;
+    let mut symbols = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.starts_with("END") && !symbols.is_empty() {
+            break;
+        }
+        if !(line.starts_with("ATOM") || line.starts_with("HETATM")) {
+            continue;
+        }
+        //element columns 77-78, falling back onto the atom name
+        let symbol = match line.get(76..78).map(|x| x.trim()) {
+            Some(symbol) if !symbol.is_empty() => symbol.to_string(),
+            _ => line
+                .get(12..16)
+                .unwrap_or("")
+                .trim()
+                .chars()
+                .take_while(|x| x.is_ascii_alphabetic())
+                .collect(),
+        };
+        symbols.push(symbol);
+    }
+    if symbols.is_empty() {
+        bail!("no atoms found in {}", path.as_ref().display());
+    }
+    Ok(symbols)
+}
+
+fn atom_records(atoms: &[Atom]) -> Vec<String> {
+    atoms
+        .iter()
+        .enumerate()
+        .map(|(index, atom)| {
+            let pos = atom.pos.as_vec();
+            let mut name = format!("{}{}", atom.symbol, index + 1);
+            name.truncate(4);
+            format!(
+                "{:<6}{:>5} {:<4} {:>3} {:1}{:>4}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}          {:>2}",
+                "HETATM",
+                (index + 1) % 100000,
+                name,
+                "MOL",
+                "A",
+                1,
+                pos.x,
+                pos.y,
+                pos.z,
+                1.0,
+                0.0,
+                atom.symbol
+            )
+        })
+        .collect()
+}
+
+//CONECT records list at most four bonded atoms per line.
+fn conect_records(atoms: &[Atom]) -> Vec<String> {
+    let mut neighbours: Vec<Vec<usize>> = vec![vec![]; atoms.len()];
+    for (i, j) in perceive_bonds(atoms) {
+        neighbours[i].push(j);
+        neighbours[j].push(i);
+    }
+    let mut records = vec![];
+    for (index, bonded) in neighbours.iter().enumerate() {
+        for chunk in bonded.chunks(4) {
+            let partners = chunk
+                .iter()
+                .map(|x| format!("{:>5}", (x + 1) % 100000))
+                .collect::<String>();
+            records.push(format!("CONECT{:>5}{}", (index + 1) % 100000, partners));
+        }
+    }
+    records
+}
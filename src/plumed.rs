@@ -0,0 +1,95 @@
+use std::fmt::Display;
+
+use anyhow::{bail, Context, Result};
+
+///Header of a PLUMED style data file, a `#! FIELDS` line naming the
+///columns and `#! SET KEY VALUE` lines, as opening HILLS, umbrella.txt
+///and steered.txt.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Header {
+    pub fields: Vec<String>,
+    sets: Vec<(String, String)>,
+}
+
+impl Header {
+    pub fn new(fields: Vec<String>) -> Header {
+        Header {
+            fields,
+            sets: vec![],
+        }
+    }
+
+    pub fn set(mut self, key: &str, value: impl Display) -> Header {
+        self.sets.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    ///mark a variable in degrees as periodic over a full turn.
+    pub fn periodic(self, name: &str) -> Header {
+        self.set(&format!("min_{}", name), -180)
+            .set(&format!("max_{}", name), 180)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.sets
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    ///numeric value of a SET key, `path` naming the file in errors.
+    pub fn number(&self, key: &str, path: &str) -> Result<Option<f64>> {
+        self.get(key)
+            .map(|x| x.parse::<f64>())
+            .transpose()
+            .with_context(|| format!("invalid {} in {}", key, path))
+    }
+
+    ///range of a periodic variable.
+    pub fn period(&self, name: &str, path: &str) -> Result<Option<(f64, f64)>> {
+        let min = self.number(&format!("min_{}", name), path)?;
+        let max = self.number(&format!("max_{}", name), path)?;
+        Ok(min.zip(max))
+    }
+
+    ///the header lines as written at the top of a file.
+    pub fn write(&self) -> String {
+        let mut lines = vec![format!("#! FIELDS {}", self.fields.join(" "))];
+        for (key, value) in &self.sets {
+            lines.push(format!("#! SET {} {}", key, value));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+///Read a PLUMED style file into its header and the numbers of its data
+///lines, each holding one value per field.
+pub fn read(path: &str) -> Result<(Header, Vec<Vec<f64>>)> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to open {}", path))?;
+    let mut header = Header::default();
+    let mut rows = vec![];
+    for line in contents.lines().filter(|x| !x.trim().is_empty()) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["#!", "FIELDS", rest @ ..] => {
+                header.fields = rest.iter().map(|x| x.to_string()).collect()
+            }
+            ["#!", "SET", key, value] => header = header.set(key, value),
+            _ if line.starts_with('#') => {}
+            _ => rows.push(
+                words
+                    .iter()
+                    .map(|x| x.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .ok()
+                    .filter(|x| x.len() == header.fields.len())
+                    .with_context(|| format!("invalid line \"{}\" in {}", line, path))?,
+            ),
+        }
+    }
+    if header.fields.is_empty() {
+        bail!("{} has no FIELDS header", path);
+    }
+    Ok((header, rows))
+}
@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Args;
+use crate::integrator::Integrator;
+use crate::report::ReportIntervals;
+use crate::restraint::RestraintDefinition;
+use crate::schedule::Schedule;
+use crate::simulation::Simulation;
+use crate::thermostat::Thermostat;
+
+///Ordered stages run one after the other, each continuing from the
+///final state of the one before.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Protocol {
+    pub stages: Vec<Stage>,
+}
+
+///Kind of a stage, deciding how the velocities are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageKind {
+    ///velocity quench towards the nearest minimum
+    Minimize,
+    ///thermostat target ramped from `start_temperature` to `temperature`
+    Heat,
+    ///thermostat held at `temperature`
+    Equilibrate,
+    ///dynamics without a thermostat unless one is given
+    Produce,
+}
+
+///A single stage of a protocol. Settings not given fall back to the
+///command line options.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Stage {
+    pub name: String,
+    pub kind: StageKind,
+    pub num_steps: usize,
+    ///time step in fs
+    pub time_step: Option<f64>,
+    pub thermostat: Option<Thermostat>,
+    ///defaults to the `--integrator` option
+    pub integrator: Option<Integrator>,
+    ///thermostat target in K
+    pub temperature: Option<f64>,
+    ///target at the start of a heat stage in K, 0 if not given
+    pub start_temperature: Option<f64>,
+    ///time dependent target, replacing `temperature`
+    pub schedule: Option<Schedule>,
+    ///draw new velocities at this temperature in K when the stage starts
+    pub velocities: Option<f64>,
+    pub report: Option<ReportIntervals>,
+    ///Gaussian16 configuration holding the route of the stage
+    pub config: Option<String>,
+    ///multiple time stepping, defaults to the `--cheap-config` option
+    pub respa: Option<RespaStage>,
+    ///restraints on internal coordinates replacing those of the stage before
+    pub restraints: Option<Vec<RestraintDefinition>>,
+}
+
+///Cheap method and inner steps of a multiple time stepping stage.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RespaStage {
+    pub config: String,
+    pub ratio: usize,
+}
+
+impl Stage {
+    ///directory the outputs of the stage are written to.
+    pub fn dir_name(&self, index: usize) -> String {
+        format!("{:02}_{}", index + 1, self.name)
+    }
+
+    ///thermostat target of the stage. Heat stages ramp linearly unless
+    ///a schedule is given.
+    pub fn schedule(&self) -> Option<Schedule> {
+        if self.schedule.is_some() {
+            return self.schedule.clone();
+        }
+        let temperature = self.temperature?;
+        Some(match self.kind {
+            StageKind::Heat => Schedule::Linear {
+                start: self.start_temperature.unwrap_or(0.0),
+                end: temperature,
+            },
+            _ => Schedule::Constant { temperature },
+        })
+    }
+}
+
+impl Protocol {
+    pub fn load(path: &str) -> Result<Protocol> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+        let protocol: Protocol = serde_yaml::from_reader(file)
+            .with_context(|| format!("failed to read protocol {}", path))?;
+        protocol.validate()?;
+        Ok(protocol)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.stages.is_empty() {
+            bail!("the protocol has no stages");
+        }
+        for (index, stage) in self.stages.iter().enumerate() {
+            if !stage
+                .name
+                .chars()
+                .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+            {
+                bail!(
+                    "stage {} name \"{}\" may only hold letters, digits, - and _",
+                    index + 1,
+                    stage.name
+                );
+            }
+            let stochastic = stage.integrator.as_ref().is_some_and(|x| x.is_stochastic());
+            let thermostatted = stage.thermostat.is_some() || stochastic;
+            let needs_thermostat = matches!(stage.kind, StageKind::Heat | StageKind::Equilibrate);
+            if needs_thermostat && !thermostatted {
+                bail!(
+                    "stage {} needs a thermostat or a Langevin integrator",
+                    stage.name
+                );
+            }
+            if thermostatted && stage.schedule().is_none() {
+                bail!(
+                    "stage {} has a thermostat but no temperature or schedule",
+                    stage.name
+                );
+            }
+            if let Some(schedule) = stage.schedule() {
+                schedule
+                    .validate()
+                    .with_context(|| format!("in stage {}", stage.name))?;
+            }
+            if stage.velocities.is_some_and(|x| x < 0.0 || !x.is_finite()) {
+                bail!("stage {} draws velocities below 0 K", stage.name);
+            }
+            if let Some(respa) = &stage.respa {
+                if respa.ratio == 0 {
+                    bail!("stage {} needs at least one inner step", stage.name);
+                }
+                if stage
+                    .integrator
+                    .as_ref()
+                    .is_some_and(|x| *x != Integrator::default())
+                {
+                    bail!(
+                        "multiple time stepping in stage {} runs on velocity Verlet",
+                        stage.name
+                    );
+                }
+            }
+            if stage.kind == StageKind::Minimize && thermostatted {
+                bail!("minimize stage {} cannot use a thermostat", stage.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+///Run the stages of a protocol, each in its own directory. On restart
+///the last stage with a checkpoint is resumed and the rest follow.
+pub fn run(path: &str, args: &Args) -> Result<()> {
+    let protocol = Protocol::load(path)?;
+    let root = std::env::current_dir()?;
+
+    let mut first = 0;
+    let mut simulation = None;
+    if args.restart {
+        let resume = protocol
+            .stages
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(index, stage)| has_checkpoint(&root.join(stage.dir_name(*index))));
+        let (index, stage) = match resume {
+            Some(x) => x,
+            None => bail!("no stage checkpoint found to restart from"),
+        };
+        std::env::set_current_dir(root.join(stage.dir_name(index)))?;
+        simulation = Some(Simulation::from_save(args)?.run()?);
+        first = index + 1;
+    }
+
+    for (index, stage) in protocol.stages.iter().enumerate().skip(first) {
+        let fresh = match simulation.take() {
+            Some(x) => x,
+            None => Simulation::new(args)?,
+        };
+        let dir = root.join(stage.dir_name(index));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        std::env::set_current_dir(&dir)?;
+        simulation = Some(fresh.begin_stage(stage, index, args, &root)?.run()?);
+    }
+    std::env::set_current_dir(root)?;
+    Ok(())
+}
+
+fn has_checkpoint(dir: &Path) -> bool {
+    std::fs::metadata(dir.join("save.json"))
+        .map(|x| x.len() > 0)
+        .unwrap_or(false)
+}
@@ -0,0 +1,22 @@
+use crate::atom::Atom;
+use crate::vectored::Force;
+
+///Energy and forces of a geometry.
+pub struct ForceOutput {
+    ///potential energy in kJ/mol
+    pub energy: f64,
+    ///energy of the restraints in kJ/mol, not part of `energy`
+    pub restraint: f64,
+    ///energy of the confining wall in kJ/mol, not part of `energy`
+    pub wall: f64,
+    ///energy of the metadynamics bias in kJ/mol, not part of `energy`
+    pub bias: f64,
+    ///SCF cycles needed, if the method reports them
+    pub cycles: Option<usize>,
+    pub forces: Vec<Force<f64>>,
+}
+
+///Anything able to compute the energy and forces of the atoms.
+pub trait ForceProvider {
+    fn compute(&self, atoms: &[Atom]) -> ForceOutput;
+}
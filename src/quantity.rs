@@ -0,0 +1,130 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+use crate::vector::Vector3D;
+use crate::vectored::{
+    Acceleration, AtomicForce, Displacement, Force, Momentum, Position, Vectored, Velocity,
+};
+
+///Time in fs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Time(f64);
+
+impl Time {
+    pub fn fs(value: f64) -> Time {
+        Time(value)
+    }
+
+    pub fn as_fs(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Add for Time {
+    type Output = Time;
+    fn add(self, rhs: Time) -> Time {
+        Time(self.0 + rhs.0)
+    }
+}
+
+///Mass in amu.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Mass(f64);
+
+impl Mass {
+    pub fn amu(value: f64) -> Mass {
+        Mass(value)
+    }
+
+    pub fn as_amu(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Add for Mass {
+    type Output = Mass;
+    fn add(self, rhs: Mass) -> Mass {
+        Mass(self.0 + rhs.0)
+    }
+}
+
+impl std::iter::Sum for Mass {
+    fn sum<I: Iterator<Item = Mass>>(iter: I) -> Mass {
+        iter.fold(Mass(0.0), |a, b| a + b)
+    }
+}
+
+//`lhs * rhs = out` and `lhs / rhs = out` between a vector quantity and
+//a scalar quantity.
+macro_rules! relation {
+    ($lhs:ident * $rhs:ident = $out:ident) => {
+        impl Mul<$rhs> for $lhs<f64> {
+            type Output = $out<f64>;
+            fn mul(self, rhs: $rhs) -> $out<f64> {
+                let vec = self.as_vec() * rhs.0;
+                $out::new(vec.x, vec.y, vec.z)
+            }
+        }
+
+        impl Mul<$lhs<f64>> for $rhs {
+            type Output = $out<f64>;
+            fn mul(self, rhs: $lhs<f64>) -> $out<f64> {
+                rhs * self
+            }
+        }
+    };
+    ($lhs:ident / $rhs:ident = $out:ident) => {
+        impl Div<$rhs> for $lhs<f64> {
+            type Output = $out<f64>;
+            fn div(self, rhs: $rhs) -> $out<f64> {
+                let vec = self.as_vec() / rhs.0;
+                $out::new(vec.x, vec.y, vec.z)
+            }
+        }
+    };
+}
+
+relation!(Velocity * Time = Displacement);
+relation!(Acceleration * Time = Velocity);
+relation!(Displacement / Time = Velocity);
+relation!(Velocity / Time = Acceleration);
+relation!(Acceleration * Mass = Force);
+relation!(Velocity * Mass = Momentum);
+relation!(Force / Mass = Acceleration);
+relation!(Momentum / Mass = Velocity);
+
+impl Add<Displacement<f64>> for Position<f64> {
+    type Output = Position<f64>;
+    fn add(self, rhs: Displacement<f64>) -> Position<f64> {
+        let vec = self.as_vec() + rhs.as_vec();
+        Position::new(vec.x, vec.y, vec.z)
+    }
+}
+
+impl Sub<Displacement<f64>> for Position<f64> {
+    type Output = Position<f64>;
+    fn sub(self, rhs: Displacement<f64>) -> Position<f64> {
+        let vec = self.as_vec() - rhs.as_vec();
+        Position::new(vec.x, vec.y, vec.z)
+    }
+}
+
+impl Sub for Position<f64> {
+    type Output = Displacement<f64>;
+    fn sub(self, rhs: Position<f64>) -> Displacement<f64> {
+        let vec = self.as_vec() - rhs.as_vec();
+        Displacement::new(vec.x, vec.y, vec.z)
+    }
+}
+
+impl From<AtomicForce<f64>> for Force<f64> {
+    //Hartree/Bohr to amu A/fs^2
+    fn from(value: AtomicForce<f64>) -> Force<f64> {
+        let vec: Vector3D<f64> = value.as_vec() * units::HARTREE_BOHR_MD_FORCE;
+        Force::new(vec.x, vec.y, vec.z)
+    }
+}
@@ -0,0 +1,169 @@
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Args;
+use crate::units::EnergyUnit;
+
+///Number of steps between successive writes of each report.
+///An interval of 0 disables the report.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReportIntervals {
+    pub trajectory: usize,
+    pub energy: usize,
+    pub velocity: usize,
+    pub kinetic: usize,
+    pub force: usize,
+}
+
+impl ReportIntervals {
+    pub fn new(args: &Args) -> ReportIntervals {
+        ReportIntervals {
+            trajectory: args.traj_every,
+            energy: args.energy_every,
+            velocity: args.vel_every,
+            kinetic: args.kin_every,
+            force: args.force_every,
+        }
+    }
+
+    ///true when a report with the given interval is due at `step`.
+    pub fn due(interval: usize, step: usize) -> bool {
+        interval != 0 && step.is_multiple_of(interval)
+    }
+}
+
+impl Default for ReportIntervals {
+    //matches the command line defaults
+    fn default() -> Self {
+        ReportIntervals {
+            trajectory: 1,
+            energy: 1,
+            velocity: 1,
+            kinetic: 1,
+            force: 1,
+        }
+    }
+}
+
+///header line opening each frame of a per-atom report.
+pub fn frame_header(step: usize, time: f64) -> String {
+    format!("# Step: {} Time: {:.2} fs", step, time)
+}
+
+///line of whitespace aligned columns 30 characters wide, without
+///trailing spaces.
+pub fn columns(columns: &[String]) -> String {
+    let mut line = columns
+        .iter()
+        .map(|x| format!("{:<30}", x))
+        .collect::<Vec<String>>()
+        .join(" ");
+    line.truncate(line.trim_end().len());
+    line + "\n"
+}
+
+///File formats of the energy report.
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EnergyFormat {
+    ///whitespace aligned columns
+    #[default]
+    Text,
+    ///comma separated values with a header row
+    Csv,
+    ///one JSON object per line
+    Json,
+}
+
+impl EnergyFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            EnergyFormat::Text => "energy.txt",
+            EnergyFormat::Csv => "energy.csv",
+            EnergyFormat::Json => "energy.json",
+        }
+    }
+
+    ///header of the energy report, JSON lines carry their unit in every row.
+    pub fn header(&self, unit: EnergyUnit) -> String {
+        let label = unit.label();
+        let columns = [
+            "Step".to_string(),
+            "Time fs".to_string(),
+            "Time step fs".to_string(),
+            "Potential ".to_string() + label,
+            "Restraint ".to_string() + label,
+            "Wall ".to_string() + label,
+            "Bias ".to_string() + label,
+            "Kinetic ".to_string() + label,
+            "Total ".to_string() + label,
+            "Temperature K".to_string(),
+            "Conserved ".to_string() + label,
+            "Drift ".to_string() + label,
+        ];
+        match self {
+            EnergyFormat::Text => self::columns(&columns),
+            EnergyFormat::Csv => columns.join(",") + "\n",
+            EnergyFormat::Json => String::new(),
+        }
+    }
+
+    pub fn row(&self, row: &EnergyRow) -> String {
+        match self {
+            EnergyFormat::Text => format!(
+                "{:<30} {:<30.2} {:<30.4} {:<30.6} {:<30.6} {:<30.6} {:<30.6} {:<30.6} {:<30.6} {:<30.2} {:<30.6} {:.6}\n",
+                row.step,
+                row.time,
+                row.time_step,
+                row.potential,
+                row.restraint,
+                row.wall,
+                row.bias,
+                row.kinetic,
+                row.total,
+                row.temperature,
+                row.conserved,
+                row.drift
+            ),
+            EnergyFormat::Csv => format!(
+                "{},{:.2},{:.4},{:.6},{:.6},{:.6},{:.6},{:.6},{:.6},{:.2},{:.6},{:.6}\n",
+                row.step,
+                row.time,
+                row.time_step,
+                row.potential,
+                row.restraint,
+                row.wall,
+                row.bias,
+                row.kinetic,
+                row.total,
+                row.temperature,
+                row.conserved,
+                row.drift
+            ),
+            EnergyFormat::Json => {
+                serde_json::to_string(row).expect("failed to serialize energy row") + "\n"
+            }
+        }
+    }
+}
+
+///A single line of the energy report, energies in `unit`.
+#[derive(Debug, Serialize)]
+pub struct EnergyRow {
+    pub step: usize,
+    pub time: f64,
+    pub time_step: f64,
+    pub potential: f64,
+    ///energy of the restraints, included in the total
+    pub restraint: f64,
+    ///energy of the confining wall, included in the total
+    pub wall: f64,
+    ///energy of the metadynamics bias, included in the total
+    pub bias: f64,
+    pub kinetic: f64,
+    pub total: f64,
+    pub temperature: f64,
+    pub conserved: f64,
+    pub drift: f64,
+    pub unit: &'static str,
+}
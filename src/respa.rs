@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::provider::{ForceOutput, ForceProvider};
+use crate::quantity::Time;
+use crate::vectored::Force;
+
+///Two level reversible RESPA, Tuckerman et al. J. Chem. Phys. 97, 1990 (1992).
+///The cheap method moves the atoms over `ratio` inner steps, the
+///difference to the expensive method is applied as an impulse at the
+///start and end of every outer step.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Respa {
+    ///Gaussian16 configuration holding the route of the cheap method
+    pub config: String,
+    ///inner steps per outer step
+    pub ratio: usize,
+    //cheap forces and expensive minus cheap forces at the current positions
+    #[serde(default)]
+    fast: Vec<Force<f64>>,
+    #[serde(default)]
+    slow: Vec<Force<f64>>,
+}
+
+impl Respa {
+    pub fn new(config: String, ratio: usize) -> Respa {
+        Respa {
+            config,
+            ratio,
+            fast: Vec::new(),
+            slow: Vec::new(),
+        }
+    }
+
+    ///advance the atoms over an outer step of `time_step`, `force` holding
+    ///the expensive forces at the current positions. Returns the expensive
+    ///output at the new positions, whose forces become the current ones.
+    pub fn step(
+        &mut self,
+        atoms: &mut [Atom],
+        time_step: Time,
+        cheap: &dyn ForceProvider,
+        expensive: &dyn ForceProvider,
+    ) -> ForceOutput {
+        if self.fast.len() != atoms.len() {
+            self.fast = cheap.compute(atoms).forces;
+            self.slow = Self::difference(atoms.iter().map(|x| x.force), &self.fast);
+        }
+        let outer = Time::fs(0.5 * time_step.as_fs());
+        let inner = Time::fs(time_step.as_fs() / self.ratio as f64);
+
+        self.kick(atoms, Kind::Slow, outer);
+        for _ in 0..self.ratio {
+            self.kick(atoms, Kind::Fast, Time::fs(0.5 * inner.as_fs()));
+            for x in atoms.iter_mut().filter(|x| x.can_mv) {
+                x.pos = x.pos + x.vel * inner;
+            }
+            self.fast = cheap.compute(atoms).forces;
+            self.kick(atoms, Kind::Fast, Time::fs(0.5 * inner.as_fs()));
+        }
+        let output = expensive.compute(atoms);
+        self.slow = Self::difference(output.forces.iter().copied(), &self.fast);
+        self.kick(atoms, Kind::Slow, outer);
+
+        for (x, force) in atoms.iter_mut().zip(output.forces.iter()) {
+            x.force = *force;
+            x.next_force = *force;
+        }
+        output
+    }
+
+    fn kick(&self, atoms: &mut [Atom], kind: Kind, time: Time) {
+        let forces = match kind {
+            Kind::Fast => &self.fast,
+            Kind::Slow => &self.slow,
+        };
+        for (x, force) in atoms.iter_mut().zip(forces.iter()) {
+            if x.can_mv {
+                x.vel = x.vel + (*force / x.mass) * time;
+            }
+        }
+    }
+
+    fn difference<I: Iterator<Item = Force<f64>>>(
+        expensive: I,
+        cheap: &[Force<f64>],
+    ) -> Vec<Force<f64>> {
+        expensive.zip(cheap.iter()).map(|(a, b)| a - *b).collect()
+    }
+}
+
+enum Kind {
+    Fast,
+    Slow,
+}
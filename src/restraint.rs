@@ -0,0 +1,349 @@
+use std::f64::consts::PI;
+use std::fs::File;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::geometry;
+use crate::metadynamics::Metadynamics;
+use crate::provider::{ForceOutput, ForceProvider};
+use crate::steering::Steering;
+use crate::umbrella::Umbrella;
+use crate::units::{self, EnergyUnit};
+use crate::vector::Vector3D;
+use crate::vectored::{Force, Position, Vectored};
+use crate::wall::Wall;
+
+///Harmonic tether of atoms to reference positions,
+///E = k/2 |r - r0|^2 summed over the atoms.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PositionRestraint {
+    pub atoms: Vec<usize>,
+    ///force constant in kJ/mol/A^2
+    pub force_constant: f64,
+    pub reference: Vec<Position<f64>>,
+}
+
+impl PositionRestraint {
+    ///tether the atoms to their current positions.
+    pub fn new(atoms: Vec<usize>, force_constant: f64, all: &[Atom]) -> PositionRestraint {
+        PositionRestraint {
+            reference: atoms.iter().map(|x| all[*x].pos).collect(),
+            atoms,
+            force_constant,
+        }
+    }
+
+    fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        let mut energy = 0.0;
+        for (&index, reference) in self.atoms.iter().zip(&self.reference) {
+            let r = (atoms[index].pos - *reference).as_vec();
+            energy += 0.5 * self.force_constant * r.dot(r);
+            let f = r * (-self.force_constant / units::MD_ENERGY_KJ_MOL);
+            forces[index] = forces[index] + Force::new(f.x, f.y, f.z);
+        }
+        energy
+    }
+}
+
+///Internal coordinates a restraint may act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Coordinate {
+    Distance,
+    Angle,
+    Dihedral,
+}
+
+impl Coordinate {
+    fn num_atoms(&self) -> usize {
+        match self {
+            Coordinate::Distance => 2,
+            Coordinate::Angle => 3,
+            Coordinate::Dihedral => 4,
+        }
+    }
+
+    ///check atoms counted from 1 for the coordinate, returning them counted from 0.
+    pub fn indices(&self, atoms: &[usize], num_atoms: usize) -> Result<Vec<usize>> {
+        if atoms.len() != self.num_atoms() {
+            bail!(
+                "a {:?} needs {} atoms, {} given",
+                self,
+                self.num_atoms(),
+                atoms.len()
+            );
+        }
+        for (n, atom) in atoms.iter().enumerate() {
+            if *atom < 1 || *atom > num_atoms {
+                bail!("atom {} is outside 1-{}", atom, num_atoms);
+            }
+            if atoms[..n].contains(atom) {
+                bail!("atom {} is given twice", atom);
+            }
+        }
+        Ok(atoms.iter().map(|x| x - 1).collect())
+    }
+
+    ///value in A or rad at the positions and its gradient with respect to each.
+    pub fn evaluate(&self, p: &[Vector3D<f64>]) -> (f64, Vec<Vector3D<f64>>) {
+        match self {
+            Coordinate::Distance => {
+                let (value, gradient) = geometry::distance(p[0], p[1]);
+                (value, gradient.to_vec())
+            }
+            Coordinate::Angle => {
+                let (value, gradient) = geometry::angle(p[0], p[1], p[2]);
+                (value, gradient.to_vec())
+            }
+            Coordinate::Dihedral => {
+                let (value, gradient) = geometry::dihedral(p[0], p[1], p[2], p[3]);
+                (value, gradient.to_vec())
+            }
+        }
+    }
+
+    ///difference of two values, dihedrals wrapped into (-pi, pi]
+    pub fn difference(&self, value: f64, target: f64) -> f64 {
+        let delta = value - target;
+        match self {
+            Coordinate::Dihedral => delta - 2.0 * PI * (delta / (2.0 * PI)).round(),
+            _ => delta,
+        }
+    }
+}
+
+///A restraint on an internal coordinate as written in a restraint file
+///or protocol stage: atoms counted from 1, the target and width in A or
+///degrees and the force constant in the output energy unit per A^2 or rad^2.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RestraintDefinition {
+    pub kind: Coordinate,
+    pub atoms: Vec<usize>,
+    pub target: f64,
+    ///half width of the flat bottom, 0 for a harmonic restraint
+    #[serde(default)]
+    pub width: f64,
+    pub force_constant: f64,
+}
+
+#[derive(Deserialize)]
+struct RestraintFile {
+    restraints: Vec<RestraintDefinition>,
+}
+
+///Harmonic restraint on an internal coordinate, flat bottomed when `width`
+///is not zero: E = k/2 (|x - x0| - w)^2 outside x0 +- w and 0 inside.
+///Atoms count from 0, lengths are in A, angles in rad and the force
+///constant in kJ/mol per A^2 or rad^2.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InternalRestraint {
+    pub kind: Coordinate,
+    pub atoms: Vec<usize>,
+    pub target: f64,
+    pub width: f64,
+    pub force_constant: f64,
+}
+
+impl InternalRestraint {
+    ///restraints of the `restraints` section of a YAML file.
+    pub fn load(path: &str, unit: EnergyUnit, num_atoms: usize) -> Result<Vec<InternalRestraint>> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+        let contents: RestraintFile = serde_yaml::from_reader(file)
+            .with_context(|| format!("failed to read restraints from {}", path))?;
+        Self::convert(&contents.restraints, unit, num_atoms)
+    }
+
+    pub fn convert(
+        definitions: &[RestraintDefinition],
+        unit: EnergyUnit,
+        num_atoms: usize,
+    ) -> Result<Vec<InternalRestraint>> {
+        definitions
+            .iter()
+            .enumerate()
+            .map(|(index, x)| {
+                Self::new(x, unit, num_atoms).with_context(|| format!("in restraint {}", index + 1))
+            })
+            .collect()
+    }
+
+    fn new(
+        x: &RestraintDefinition,
+        unit: EnergyUnit,
+        num_atoms: usize,
+    ) -> Result<InternalRestraint> {
+        let atoms = x.kind.indices(&x.atoms, num_atoms)?;
+        if x.force_constant < 0.0 || x.width < 0.0 {
+            bail!("the force constant and width cannot be negative");
+        }
+        let scale = match x.kind {
+            Coordinate::Distance => 1.0,
+            _ => PI / 180.0,
+        };
+        Ok(InternalRestraint {
+            kind: x.kind,
+            atoms,
+            target: x.target * scale,
+            width: x.width * scale,
+            force_constant: x.force_constant / unit.convert(1.0),
+        })
+    }
+
+    ///current value of the coordinate in A or rad.
+    pub fn value(&self, atoms: &[Atom]) -> f64 {
+        self.kind.evaluate(&self.positions(atoms)).0
+    }
+
+    fn positions(&self, atoms: &[Atom]) -> Vec<Vector3D<f64>> {
+        self.atoms.iter().map(|x| atoms[*x].pos.as_vec()).collect()
+    }
+
+    fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        let (value, gradient) = self.kind.evaluate(&self.positions(atoms));
+        let delta = self.kind.difference(value, self.target);
+        let excess = delta.signum() * (delta.abs() - self.width).max(0.0);
+        let slope = self.force_constant * excess;
+        for (&index, g) in self.atoms.iter().zip(gradient) {
+            let f = g * (-slope / units::MD_ENERGY_KJ_MOL);
+            forces[index] = forces[index] + Force::new(f.x, f.y, f.z);
+        }
+        0.5 * self.force_constant * excess * excess
+    }
+}
+
+///Restraints adding a bias on top of the forces of the electronic structure.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Restraints {
+    #[serde(default)]
+    pub positions: Vec<PositionRestraint>,
+    #[serde(default)]
+    pub internal: Vec<InternalRestraint>,
+    #[serde(default)]
+    pub wall: Option<Wall>,
+    #[serde(default)]
+    pub metadynamics: Option<Metadynamics>,
+    #[serde(default)]
+    pub umbrella: Option<Umbrella>,
+    #[serde(default)]
+    pub steering: Option<Steering>,
+}
+
+impl Restraints {
+    ///whether atoms are tied to fixed points in space, by position
+    ///restraints or a wall with a fixed centre.
+    pub fn anchors(&self) -> bool {
+        !self.positions.is_empty() || matches!(&self.wall, Some(x) if x.centre.is_some())
+    }
+
+    ///add the restraint forces in amu A/fs^2 to `forces`, returning the
+    ///restraint energy in kJ/mol.
+    pub fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        let positions: f64 = self.positions.iter().map(|x| x.apply(atoms, forces)).sum();
+        let internal: f64 = self.internal.iter().map(|x| x.apply(atoms, forces)).sum();
+        let umbrella = match &self.umbrella {
+            Some(x) => x.apply(atoms, forces),
+            None => 0.0,
+        };
+        let steering = match &self.steering {
+            Some(x) => x.apply(atoms, forces),
+            None => 0.0,
+        };
+        positions + internal + umbrella + steering
+    }
+}
+
+///A force provider with the restraints added on top.
+pub struct Restrained<'a, P: ForceProvider> {
+    inner: P,
+    restraints: &'a Restraints,
+}
+
+impl<'a, P: ForceProvider> Restrained<'a, P> {
+    pub fn new(inner: P, restraints: &'a Restraints) -> Restrained<'a, P> {
+        Restrained { inner, restraints }
+    }
+}
+
+impl<P: ForceProvider> ForceProvider for Restrained<'_, P> {
+    fn compute(&self, atoms: &[Atom]) -> ForceOutput {
+        let mut output = self.inner.compute(atoms);
+        output.restraint = self.restraints.apply(atoms, &mut output.forces);
+        if let Some(wall) = &self.restraints.wall {
+            output.wall = wall.apply(atoms, &mut output.forces);
+        }
+        if let Some(metadynamics) = &self.restraints.metadynamics {
+            output.bias = metadynamics.apply(atoms, &mut output.forces);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::geometry::check_gradient;
+
+    fn points() -> Vec<Vector3D<f64>> {
+        [
+            [1.0, 0.8, 0.1],
+            [0.0, 0.0, 0.0],
+            [0.1, -0.2, 1.5],
+            [-0.7, 0.9, 2.0],
+        ]
+        .map(|[x, y, z]| Vector3D::new(x, y, z))
+        .to_vec()
+    }
+
+    //energy in kJ/mol of `apply` at the points and its gradient from the forces
+    fn energy(
+        apply: impl Fn(&[Atom], &mut [Force<f64>]) -> f64,
+    ) -> impl Fn(&[Vector3D<f64>]) -> (f64, Vec<Vector3D<f64>>) {
+        move |points| {
+            let mut atoms = test_atoms(&[("C", [0.0; 3]); 4]);
+            for (atom, p) in atoms.iter_mut().zip(points) {
+                atom.pos = Position::new(p.x, p.y, p.z);
+            }
+            let mut forces = vec![Force::new(0.0, 0.0, 0.0); atoms.len()];
+            let energy = apply(&atoms, &mut forces);
+            let gradient = forces
+                .iter()
+                .map(|x| x.as_vec() * -units::MD_ENERGY_KJ_MOL)
+                .collect();
+            (energy, gradient)
+        }
+    }
+
+    #[test]
+    fn position_restraint_gradient() {
+        let atoms = test_atoms(&[("C", [0.0; 3]), ("C", [0.5, 0.5, 0.5])]);
+        let restraint = PositionRestraint::new(vec![0, 1], 50.0, &atoms);
+        check_gradient(energy(|a, f| restraint.apply(a, f)), &points(), None);
+    }
+
+    #[test]
+    fn internal_restraint_gradients() {
+        let cases = [
+            (Coordinate::Distance, 1.0, 0.0),
+            (Coordinate::Distance, 1.0, 0.2),
+            (Coordinate::Angle, 1.2, 0.0),
+            (Coordinate::Angle, 2.5, 0.1),
+            (Coordinate::Dihedral, 3.0, 0.0),
+            (Coordinate::Dihedral, -2.0, 0.3),
+        ];
+        for (kind, target, width) in cases {
+            let restraint = InternalRestraint {
+                kind,
+                atoms: (0..kind.num_atoms()).collect(),
+                target,
+                width,
+                force_constant: 200.0,
+            };
+            let f = energy(|a, f| restraint.apply(a, f));
+            assert!(f(&points()).0 > 0.0, "{:?} is inside its flat bottom", kind);
+            check_gradient(f, &points(), None);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+///Thermostat target temperature in K as a function of the step.
+///Ramps run over the whole length of the run or stage.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Schedule {
+    Constant {
+        temperature: f64,
+    },
+    ///linear ramp from `start` to `end`
+    Linear {
+        start: f64,
+        end: f64,
+    },
+    ///each temperature held for `hold` steps, the last one to the end
+    Stepwise {
+        temperatures: Vec<f64>,
+        hold: usize,
+    },
+    ///geometric cooling from `start` to `end`
+    Exponential {
+        start: f64,
+        end: f64,
+    },
+    ///cosine cycles between `low` and `high`, `period` steps long, starting at `low`
+    Cyclic {
+        low: f64,
+        high: f64,
+        period: usize,
+    },
+}
+
+impl Schedule {
+    pub fn temperature(&self, step: usize, num_steps: usize) -> f64 {
+        let fraction = match num_steps {
+            0 => 1.0,
+            _ => (step as f64 / num_steps as f64).min(1.0),
+        };
+        match self {
+            Schedule::Constant { temperature } => *temperature,
+            Schedule::Linear { start, end } => start + (end - start) * fraction,
+            Schedule::Stepwise { temperatures, hold } => {
+                temperatures[(step / hold).min(temperatures.len() - 1)]
+            }
+            Schedule::Exponential { start, end } => start * (end / start).powf(fraction),
+            Schedule::Cyclic { low, high, period } => {
+                let phase = (step % period) as f64 / *period as f64;
+                low + (high - low) * 0.5 * (1.0 - (2.0 * PI * phase).cos())
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let temperatures = match self {
+            Schedule::Constant { temperature } => vec![*temperature],
+            Schedule::Linear { start, end } | Schedule::Exponential { start, end } => {
+                vec![*start, *end]
+            }
+            Schedule::Stepwise { temperatures, .. } => temperatures.clone(),
+            Schedule::Cyclic { low, high, .. } => vec![*low, *high],
+        };
+        if temperatures.iter().any(|x| *x < 0.0 || !x.is_finite()) {
+            bail!("schedule temperatures cannot be below 0 K");
+        }
+        match self {
+            Schedule::Stepwise { temperatures, .. } if temperatures.is_empty() => {
+                bail!("a stepwise schedule needs at least one temperature")
+            }
+            Schedule::Stepwise { hold: 0, .. } => bail!("a stepwise schedule needs a hold"),
+            Schedule::Exponential { start, end } if *start <= 0.0 || *end <= 0.0 => {
+                bail!("an exponential schedule needs positive temperatures")
+            }
+            Schedule::Cyclic { period: 0, .. } => bail!("a cyclic schedule needs a period"),
+            _ => Ok(()),
+        }
+    }
+}
+
+///Parse a schedule from the command line: a bare temperature, or
+///`linear:START:END`, `stepwise:T1,T2,...:HOLD`, `exponential:START:END`
+///and `cyclic:LOW:HIGH:PERIOD`.
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Schedule> {
+        let parts = value.split(':').collect::<Vec<&str>>();
+        let number = |x: &str| {
+            x.trim()
+                .parse::<f64>()
+                .with_context(|| format!("invalid temperature \"{}\" in schedule", x))
+        };
+        let count = |x: &str| {
+            x.trim()
+                .parse::<usize>()
+                .with_context(|| format!("invalid step count \"{}\" in schedule", x))
+        };
+        let schedule = match parts.as_slice() {
+            [temperature] => Schedule::Constant {
+                temperature: number(temperature)?,
+            },
+            ["linear", start, end] => Schedule::Linear {
+                start: number(start)?,
+                end: number(end)?,
+            },
+            ["stepwise", temperatures, hold] => Schedule::Stepwise {
+                temperatures: temperatures
+                    .split(',')
+                    .map(number)
+                    .collect::<Result<Vec<f64>>>()?,
+                hold: count(hold)?,
+            },
+            ["exponential", start, end] => Schedule::Exponential {
+                start: number(start)?,
+                end: number(end)?,
+            },
+            ["cyclic", low, high, period] => Schedule::Cyclic {
+                low: number(low)?,
+                high: number(high)?,
+                period: count(period)?,
+            },
+            _ => bail!("unknown temperature schedule \"{}\"", value),
+        };
+        schedule.validate()?;
+        Ok(schedule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Schedule {
+        value.parse().unwrap()
+    }
+
+    fn error(value: &str) -> String {
+        value.parse::<Schedule>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_every_kind() {
+        assert_eq!(parse("300"), Schedule::Constant { temperature: 300.0 });
+        assert_eq!(
+            parse("linear:0:300"),
+            Schedule::Linear {
+                start: 0.0,
+                end: 300.0
+            }
+        );
+        assert_eq!(
+            parse("stepwise:100, 200,300:50"),
+            Schedule::Stepwise {
+                temperatures: vec![100.0, 200.0, 300.0],
+                hold: 50
+            }
+        );
+        assert_eq!(
+            parse("exponential:1000:50"),
+            Schedule::Exponential {
+                start: 1000.0,
+                end: 50.0
+            }
+        );
+        assert_eq!(
+            parse("cyclic:300:600:1000"),
+            Schedule::Cyclic {
+                low: 300.0,
+                high: 600.0,
+                period: 1000
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        assert!(error("sine:300:600").starts_with("unknown temperature schedule"));
+        assert!(error("linear:300").starts_with("unknown temperature schedule"));
+        assert!(error("linear:hot:300").starts_with("invalid temperature \"hot\""));
+        assert!(error("stepwise:300:-5").starts_with("invalid step count \"-5\""));
+        assert!(error("-10").contains("below 0 K"));
+        assert!(error("linear:300:inf").contains("below 0 K"));
+        assert!(error("stepwise:300,400:0").contains("needs a hold"));
+        assert!(error("exponential:0:300").contains("positive temperatures"));
+        assert!(error("cyclic:300:600:0").contains("needs a period"));
+        let empty = Schedule::Stepwise {
+            temperatures: vec![],
+            hold: 10,
+        };
+        assert!(empty.validate().is_err());
+    }
+
+    #[test]
+    fn temperatures_along_the_run() {
+        let linear = parse("linear:0:300");
+        //the run starts at the first temperature, here at rest
+        assert_eq!(linear.temperature(0, 100), 0.0);
+        assert_eq!(linear.temperature(50, 100), 150.0);
+        assert_eq!(linear.temperature(150, 100), 300.0);
+        assert_eq!(linear.temperature(0, 0), 300.0);
+
+        let stepwise = parse("stepwise:100,200:10");
+        let steps = [0, 9, 10, 19, 500].map(|x| stepwise.temperature(x, 100));
+        assert_eq!(steps, [100.0, 100.0, 200.0, 200.0, 200.0]);
+
+        let exponential = parse("exponential:1000:10");
+        assert!((exponential.temperature(50, 100) - 100.0).abs() < 1e-9);
+        assert!((exponential.temperature(100, 100) - 10.0).abs() < 1e-9);
+
+        let cyclic = parse("cyclic:300:600:100");
+        let steps = [0, 25, 50, 100].map(|x| cyclic.temperature(x, 1000));
+        for (value, expected) in steps.iter().zip([300.0, 450.0, 600.0, 300.0]) {
+            assert!((value - expected).abs() < 1e-9, "{:?}", steps);
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+use crate::atom::Atom;
+use crate::vectored::Vectored;
+
+const KEYWORDS: [&str; 7] = ["and", "or", "not", "all", "element", "within", "of"];
+
+///A selection of atoms, parsed from
+///- indices and ranges counted from 1, `1-4,6 8`
+///- `all`
+///- `element H C`, atoms of the given elements
+///- `within 3.0 of SELECTION`, atoms within 3.0 A of any selected atom
+///- the name of a group defined with `--group`
+///
+///combined with `not`, `and`, `or` in order of precedence and parentheses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    text: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    All,
+    //inclusive ranges counted from 1
+    Indices(Vec<(usize, usize)>),
+    Element(Vec<String>),
+    Within(f64, Box<Expr>),
+    Group(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl FromStr for Selection {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Selection> {
+        //one message, as clap shows no error causes
+        let expr = tokenize(text)
+            .and_then(|tokens| Parser { tokens, pos: 0 }.parse())
+            .map_err(|e| anyhow!("invalid selection \"{}\": {}", text, e))?;
+        Ok(Selection {
+            text: text.to_string(),
+            expr,
+        })
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+///Evaluates selections on the atoms of a simulation, knowing the named groups.
+pub struct Selector<'a> {
+    atoms: &'a [Atom],
+    groups: HashMap<String, Vec<bool>>,
+}
+
+impl<'a> Selector<'a> {
+    ///`groups` holds definitions `NAME=SELECTION`, which may use the
+    ///groups defined before them.
+    pub fn new(atoms: &'a [Atom], groups: &[String]) -> Result<Selector<'a>> {
+        let mut selector = Selector {
+            atoms,
+            groups: HashMap::new(),
+        };
+        for definition in groups {
+            let (name, selection) = definition.split_once('=').with_context(|| {
+                format!("invalid group \"{}\", expected NAME=SELECTION", definition)
+            })?;
+            let name = name.trim();
+            let valid = name.starts_with(|x: char| x.is_ascii_alphabetic())
+                && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_');
+            if !valid || KEYWORDS.contains(&name) {
+                bail!("invalid group name \"{}\"", name);
+            }
+            if selector.groups.contains_key(name) {
+                bail!("group \"{}\" is defined twice", name);
+            }
+            let selection: Selection = selection.parse()?;
+            let mask = selector
+                .mask(&selection.expr)
+                .with_context(|| format!("in group \"{}\"", name))?;
+            selector.groups.insert(name.to_string(), mask);
+        }
+        Ok(selector)
+    }
+
+    ///indices of the selected atoms counted from 0, in order. Fails when
+    ///the selection refers to missing atoms or groups, or selects nothing.
+    pub fn select(&self, selection: &Selection) -> Result<Vec<usize>> {
+        let mask = self
+            .mask(&selection.expr)
+            .with_context(|| format!("in selection \"{}\"", selection))?;
+        let indices: Vec<usize> = (0..mask.len()).filter(|x| mask[*x]).collect();
+        if indices.is_empty() {
+            bail!("selection \"{}\" matches no atoms", selection);
+        }
+        Ok(indices)
+    }
+
+    fn mask(&self, expr: &Expr) -> Result<Vec<bool>> {
+        let count = self.atoms.len();
+        let mask = match expr {
+            Expr::All => vec![true; count],
+            Expr::Indices(ranges) => {
+                let mut mask = vec![false; count];
+                for &(low, high) in ranges {
+                    if high > count {
+                        bail!("atom {} is outside 1-{}", high, count);
+                    }
+                    mask[low - 1..high].iter_mut().for_each(|x| *x = true);
+                }
+                mask
+            }
+            Expr::Element(symbols) => self
+                .atoms
+                .iter()
+                .map(|x| symbols.iter().any(|s| s.eq_ignore_ascii_case(&x.symbol)))
+                .collect(),
+            Expr::Within(distance, inner) => {
+                let inner = self.mask(inner)?;
+                self.atoms
+                    .iter()
+                    .map(|x| {
+                        self.atoms
+                            .iter()
+                            .zip(&inner)
+                            .any(|(y, selected)| *selected && (x.pos - y.pos).norm() <= *distance)
+                    })
+                    .collect()
+            }
+            Expr::Group(name) => self
+                .groups
+                .get(name)
+                .cloned()
+                .with_context(|| format!("unknown group \"{}\"", name))?,
+            Expr::Not(inner) => self.mask(inner)?.into_iter().map(|x| !x).collect(),
+            Expr::And(a, b) => combine(self.mask(a)?, self.mask(b)?, |x, y| x && y),
+            Expr::Or(a, b) => combine(self.mask(a)?, self.mask(b)?, |x, y| x || y),
+        };
+        Ok(mask)
+    }
+}
+
+fn combine(a: Vec<bool>, b: Vec<bool>, op: fn(bool, bool) -> bool) -> Vec<bool> {
+    a.into_iter().zip(b).map(|(x, y)| op(x, y)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Word(String),
+    Dash,
+    Comma,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(x) | Token::Word(x) => write!(f, "\"{}\"", x),
+            Token::Dash => write!(f, "\"-\""),
+            Token::Comma => write!(f, "\",\""),
+            Token::Open => write!(f, "\"(\""),
+            Token::Close => write!(f, "\")\""),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let take = |i: &mut usize, f: fn(char) -> bool| {
+            let start = *i;
+            while *i < chars.len() && f(chars[*i]) {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>()
+        };
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '-' => {
+                tokens.push(Token::Dash);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            _ if c.is_ascii_digit() || c == '.' => tokens.push(Token::Number(take(&mut i, |x| {
+                x.is_ascii_digit() || x == '.'
+            }))),
+            _ if c.is_ascii_alphabetic() => tokens.push(Token::Word(take(&mut i, |x| {
+                x.is_ascii_alphanumeric() || x == '_'
+            }))),
+            _ => bail!("unexpected character '{}'", c),
+        }
+    }
+    if tokens.is_empty() {
+        bail!("the selection is empty");
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Expr> {
+        let expr = self.or()?;
+        match self.peek() {
+            Some(token) => Err(anyhow!("unexpected {}", token)),
+            None => Ok(expr),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(x)) if x == word)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        match self.tokens.get(self.pos) {
+            Some(token) => anyhow!("{} near {}", message, token),
+            None => anyhow!("{} at the end", message),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.is_word("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.is_word("and") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.is_word("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.peek().cloned() {
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => {
+                        self.pos -= 1;
+                        Err(self.error("expected \")\""))
+                    }
+                }
+            }
+            Some(Token::Number(_)) => self.indices(),
+            Some(Token::Word(word)) => {
+                self.pos += 1;
+                match word.as_str() {
+                    "all" => Ok(Expr::All),
+                    "element" => self.element(),
+                    "within" => self.within(),
+                    _ if KEYWORDS.contains(&word.as_str()) => {
+                        Err(anyhow!("unexpected \"{}\"", word))
+                    }
+                    _ => Ok(Expr::Group(word)),
+                }
+            }
+            Some(token) => Err(anyhow!("unexpected {}", token)),
+            None => Err(self.error("expected atoms")),
+        }
+    }
+
+    //indices and ranges separated by commas or spaces
+    fn indices(&mut self) -> Result<Expr> {
+        let mut ranges = vec![self.range()?];
+        loop {
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.pos += 1;
+                    ranges.push(self.range()?);
+                }
+                Some(Token::Number(_)) => ranges.push(self.range()?),
+                _ => break,
+            }
+        }
+        Ok(Expr::Indices(ranges))
+    }
+
+    fn range(&mut self) -> Result<(usize, usize)> {
+        let low = self.index()?;
+        if self.peek() != Some(&Token::Dash) {
+            return Ok((low, low));
+        }
+        self.pos += 1;
+        let high = self.index()?;
+        if high < low {
+            self.pos -= 1;
+            return Err(self.error(&format!("range {}-{} runs backwards", low, high)));
+        }
+        Ok((low, high))
+    }
+
+    fn index(&mut self) -> Result<usize> {
+        match self.peek().cloned() {
+            Some(Token::Number(x)) => match x.parse::<usize>() {
+                Ok(0) => Err(self.error("atoms are counted from 1")),
+                Ok(value) => {
+                    self.pos += 1;
+                    Ok(value)
+                }
+                Err(_) => Err(self.error(&format!("\"{}\" is not an atom index", x))),
+            },
+            _ => Err(self.error("expected an atom index")),
+        }
+    }
+
+    fn element(&mut self) -> Result<Expr> {
+        let mut symbols = vec![];
+        while let Some(Token::Word(word)) = self.peek().cloned() {
+            if KEYWORDS.contains(&word.as_str()) {
+                break;
+            }
+            symbols.push(word);
+            self.pos += 1;
+        }
+        if symbols.is_empty() {
+            return Err(self.error("expected element symbols after \"element\""));
+        }
+        Ok(Expr::Element(symbols))
+    }
+
+    fn within(&mut self) -> Result<Expr> {
+        let distance = match self.peek().cloned() {
+            Some(Token::Number(x)) => x
+                .parse::<f64>()
+                .ok()
+                .filter(|x| *x > 0.0)
+                .ok_or_else(|| self.error(&format!("\"{}\" is not a positive distance", x)))?,
+            _ => return Err(self.error("expected a distance in A after \"within\"")),
+        };
+        self.pos += 1;
+        if !self.is_word("of") {
+            return Err(self.error("expected \"of\" after the distance"));
+        }
+        self.pos += 1;
+        Ok(Expr::Within(distance, Box::new(self.not()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+
+    //a water molecule and a carbon atom 5 A away
+    fn atoms() -> Vec<Atom> {
+        test_atoms(&[
+            ("O", [0.0, 0.0, 0.0]),
+            ("H", [0.96, 0.0, 0.0]),
+            ("H", [-0.24, 0.93, 0.0]),
+            ("C", [5.0, 0.0, 0.0]),
+        ])
+    }
+
+    fn select(text: &str) -> Vec<usize> {
+        let atoms = atoms();
+        let groups = ["water=1-3".to_string()];
+        Selector::new(&atoms, &groups)
+            .unwrap()
+            .select(&text.parse().unwrap())
+            .unwrap()
+    }
+
+    //full message of a selection that fails to parse or to evaluate
+    fn error(text: &str) -> String {
+        let atoms = atoms();
+        let result = text
+            .parse::<Selection>()
+            .and_then(|x| Selector::new(&atoms, &[])?.select(&x));
+        format!("{:#}", result.unwrap_err())
+    }
+
+    #[test]
+    fn indices_and_ranges() {
+        assert_eq!(select("1-2,4"), vec![0, 1, 3]);
+        assert_eq!(select("3 1"), vec![0, 2]);
+        assert_eq!(select("2-2"), vec![1]);
+        assert_eq!(select("all"), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(select("1 or 2 and 3"), vec![0]);
+        assert_eq!(select("(1 or 2) and 2-4"), vec![1]);
+        assert_eq!(select("not element H and 1-3"), vec![0]);
+        assert_eq!(select("not (element H and 1-3)"), vec![0, 3]);
+        assert_eq!(select("not not 4"), vec![3]);
+    }
+
+    #[test]
+    fn elements_stop_at_keywords() {
+        assert_eq!(select("element h"), vec![1, 2]);
+        assert_eq!(select("element O C"), vec![0, 3]);
+        assert_eq!(select("element H or 4"), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn within() {
+        assert_eq!(select("within 1.0 of 1"), vec![0, 1, 2]);
+        assert_eq!(select("within 1.0 of 1 and element H"), vec![1, 2]);
+        assert_eq!(select("within 4.5 of 2 and not water"), vec![3]);
+    }
+
+    #[test]
+    fn groups() {
+        let atoms = atoms();
+        let groups = [
+            "water=1-3".to_string(),
+            "heavy=water and not element H".to_string(),
+        ];
+        let selector = Selector::new(&atoms, &groups).unwrap();
+        assert_eq!(selector.select(&"heavy".parse().unwrap()).unwrap(), vec![0]);
+        assert_eq!(select("not water"), vec![3]);
+        for name in ["and", "1x", "a-b"] {
+            let group = format!("{}=1", name);
+            assert!(Selector::new(&atoms, &[group]).is_err());
+        }
+        let twice = ["a=1".to_string(), "a=2".to_string()];
+        assert!(Selector::new(&atoms, &twice).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("5", "atom 5 is outside 1-4"),
+            ("3-1", "range 3-1 runs backwards"),
+            ("0", "atoms are counted from 1 near \"0\""),
+            (
+                "within -1 of 2",
+                "expected a distance in A after \"within\" near \"-\"",
+            ),
+            ("within 0 of 2", "\"0\" is not a positive distance"),
+            (
+                "within 2 1",
+                "expected \"of\" after the distance near \"1\"",
+            ),
+            ("(1", "expected \")\" at the end"),
+            ("1)", "unexpected \")\""),
+            ("1 and", "expected atoms at the end"),
+            (
+                "element",
+                "expected element symbols after \"element\" at the end",
+            ),
+            ("and 1", "unexpected \"and\""),
+            ("1.5", "\"1.5\" is not an atom index"),
+            ("1 $", "unexpected character '$'"),
+            ("  ", "the selection is empty"),
+            ("water", "unknown group \"water\""),
+            ("4 and not element C", "matches no atoms"),
+        ];
+        for (text, message) in cases {
+            let error = error(text);
+            assert!(error.contains(message), "\"{}\" gave \"{}\"", text, error);
+        }
+    }
+}
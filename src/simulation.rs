@@ -1,131 +1,587 @@
 use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::io::{BufRead, BufReader};
-use std::io::{Read, Write};
 use std::path::Path;
+use std::time::Instant;
 
+use crate::quantity::Time;
+use crate::respa::Respa;
+use crate::restraint::{Coordinate, InternalRestraint, PositionRestraint, Restrained, Restraints};
 use crate::vectored::{Force, Position, Vectored, Velocity};
-use anyhow::Result;
-use regex::Regex;
-use rgaussian16::Gaussian;
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::atom::{Atom, AtomFactory};
+use crate::adaptive::AdaptiveStep;
+use crate::atom::{self, Atom, AtomFactory};
 use crate::cli::Args;
+use crate::colvar::Colvar;
+use crate::constraint::{AxisFix, Constraints};
+use crate::gaussian::GaussianProvider;
+use crate::integrator::{Integrator, StepContext};
+use crate::logger::{Logger, StepRecord};
+use crate::metadynamics::Metadynamics;
+use crate::momentum;
+use crate::protocol::{Stage, StageKind};
+use crate::provider::{ForceOutput, ForceProvider};
+use crate::report::{self, frame_header, EnergyFormat, EnergyRow, ReportIntervals};
+use crate::schedule::Schedule;
+use crate::selection::Selector;
+use crate::steering::Steering;
+use crate::thermostat::Thermostat;
+use crate::trajectory::{Frame, TrajFormat};
+use crate::umbrella::Umbrella;
+use crate::units::{self, EnergyUnit};
+use crate::wall::Wall;
+use crate::watchdog::{Observation, TripAction, Watchdog};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Simulation {
     atoms: Vec<Atom>,
-    time_step: f64,
+    time_step: Time,
     num_steps: usize,
     step_num: usize,
+    //simulated time, the time step may change during a run
+    #[serde(default)]
+    elapsed: Time,
     pot_energy: f64,
+    //energy of the restraints, part of the total energy
+    #[serde(default)]
+    restraint_energy: f64,
+    //energy of the confining wall, part of the total energy
+    #[serde(default)]
+    wall_energy: f64,
+    //energy of the metadynamics bias, part of the total energy
+    #[serde(default)]
+    bias_energy: f64,
     kin_energy: f64,
     tot_energy: f64,
+    #[serde(default)]
+    init_energy: f64,
+    #[serde(default)]
+    report: ReportIntervals,
+    #[serde(default = "default_traj_formats")]
+    traj_formats: Vec<TrajFormat>,
+    #[serde(default)]
+    energy_unit: EnergyUnit,
+    #[serde(default)]
+    energy_format: EnergyFormat,
+    //kinetic energy in kJ/mol taken out of the system by thermostats,
+    //added back onto the total energy to give the conserved quantity.
+    #[serde(default)]
+    bath_energy: f64,
+    #[serde(default)]
+    watchdog: Watchdog,
+    //steps between removals of the overall linear and angular momentum, 0 never
+    #[serde(default)]
+    momentum_every: usize,
+    #[serde(default)]
+    thermostat: Option<Thermostat>,
+    //thermostat target, evaluated at step_num so a restart resumes the schedule
+    #[serde(default)]
+    schedule: Option<Schedule>,
+    //zero the velocity of atoms moving against their force
+    #[serde(default)]
+    quench: bool,
+    #[serde(default = "default_gaussian_config")]
+    gaussian_config: String,
+    #[serde(default)]
+    integrator: Integrator,
+    //multiple time stepping with a cheap inner method
+    #[serde(default)]
+    respa: Option<Respa>,
+    #[serde(default)]
+    adaptive: Option<AdaptiveStep>,
+    #[serde(default)]
+    constraints: Constraints,
+    #[serde(default)]
+    restraints: Restraints,
+    #[serde(default)]
+    colvars: Vec<Colvar>,
+    //atoms outside the flat bottom of the wall after the last step
+    #[serde(default)]
+    touching: Vec<bool>,
+    #[serde(skip)]
+    logger: Logger,
 }
 
 impl Simulation {
     pub fn new(args: &Args) -> Result<Simulation> {
-        let file = File::open(&args.input)?;
-        let time_step = args.time_step;
+        let logger = Logger::new(args.verbosity, args.log_file.clone());
+        let input = args.input.as_ref().context("no input file given")?;
+        let file = File::open(input)?;
+        let time_step = Time::fs(args.time_step);
         let num_steps = args.num_steps;
+        let report = ReportIntervals::new(args);
         let mut atoms = AtomFactory::new(file).gn_atoms()?;
-        if let Some(value) = &args.freeze {
-            Self::validate_string(value.to_owned())?;
-            atoms = Self::freeze_atoms(&atoms, value.to_owned());
+        logger.info(&format!("Read {} atoms from {}", atoms.len(), input));
+        for atom in &atoms {
+            logger.debug(&format!(
+                "{} {:.6} {:.6} {:.6}",
+                atom.symbol,
+                atom.pos.as_vec().x,
+                atom.pos.as_vec().y,
+                atom.pos.as_vec().z
+            ));
+        }
+        let selector = Selector::new(&atoms, &args.group)?;
+        let frozen = match &args.freeze {
+            Some(selection) => selector.select(selection)?,
+            None => vec![],
+        };
+        let axes = args
+            .fix_axes
+            .iter()
+            .map(|x| {
+                let (group, axes) = x.rsplit_once(':').with_context(|| {
+                    format!("invalid --fix-axes \"{}\", expected ATOMS:AXES", x)
+                })?;
+                AxisFix::new(selector.select(&group.parse()?)?, axes)
+            })
+            .collect::<Result<Vec<AxisFix>>>()?;
+        let centroids = args
+            .fix_centroid
+            .iter()
+            .map(|x| selector.select(x))
+            .collect::<Result<Vec<Vec<usize>>>>()?;
+        let rigid = args
+            .rigid
+            .iter()
+            .map(|x| selector.select(x))
+            .collect::<Result<Vec<Vec<usize>>>>()?;
+        let mut pairs = vec![];
+        for x in &args.constrain {
+            let (a, b) = x
+                .split_once(':')
+                .with_context(|| format!("invalid --constrain \"{}\", expected ATOMS:ATOMS", x))?;
+            let (a, b) = (selector.select(&a.parse()?)?, selector.select(&b.parse()?)?);
+            let before = pairs.len();
+            for &i in &a {
+                pairs.extend(b.iter().filter(|j| **j != i).map(|j| (i, *j)));
+            }
+            if pairs.len() == before {
+                bail!("constraint \"{}\" joins an atom to itself", x);
+            }
+        }
+        let mut restraints = Restraints {
+            positions: args
+                .restrain
+                .iter()
+                .map(|x| {
+                    let (group, k) = x.rsplit_once(':').with_context(|| {
+                        format!("invalid --restrain \"{}\", expected ATOMS:K", x)
+                    })?;
+                    let k = k
+                        .trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|x| *x > 0.0)
+                        .with_context(|| format!("invalid force constant \"{}\"", k))?;
+                    let k = k / args.energy_unit.convert(1.0);
+                    Ok(PositionRestraint::new(
+                        selector.select(&group.parse()?)?,
+                        k,
+                        &atoms,
+                    ))
+                })
+                .collect::<Result<Vec<PositionRestraint>>>()?,
+            internal: match &args.restraints {
+                Some(path) => InternalRestraint::load(path, args.energy_unit, atoms.len())?,
+                None => vec![],
+            },
+            wall: match &args.wall {
+                Some(spec) => Some(Wall::new(
+                    spec,
+                    args.wall_centre.as_deref(),
+                    args.energy_unit,
+                )?),
+                None => None,
+            },
+            metadynamics: None,
+            umbrella: None,
+            steering: None,
+        };
+        let colvars = match &args.colvars {
+            Some(path) => Colvar::load(path, &atoms, &selector)?,
+            None => vec![],
         };
+        restraints.metadynamics = Metadynamics::new(args, &colvars)?;
+        restraints.steering = Steering::new(args, &colvars, &atoms)?;
+        if let Some(steering) = &restraints.steering {
+            logger.info(&steering.describe());
+        }
+        Self::freeze_atoms(&mut atoms, &frozen);
 
         atoms
-            .clone()
-            .into_iter()
-            .filter(|x| x.can_mv == false)
-            .for_each(|x| println!("Atom: {} is frozen", x.symbol));
+            .iter()
+            .filter(|x| !x.can_mv)
+            .for_each(|x| logger.info(&format!("Atom: {} is frozen", x.symbol)));
+
+        let constraints = Constraints::new(&atoms, args.constrain_h, &pairs, args.shake_tolerance)?
+            .restrict(&atoms, axes, centroids, rigid)?;
+        if !constraints.bonds.is_empty() {
+            logger.info(&format!(
+                "Holding {} bond lengths fixed",
+                constraints.bonds.len()
+            ));
+        }
+        let anchored =
+            constraints.anchors() || restraints.anchors() || atoms.iter().any(|x| !x.can_mv);
+        if args.remove_momentum != 0 && anchored {
+            logger
+                .warn("Fixed or restrained atoms anchor the system, momentum will not be removed");
+        }
 
-        Ok(Simulation {
+        for (index, format) in args.traj_format.iter().enumerate() {
+            if args.traj_format[..index]
+                .iter()
+                .any(|x| x.file_name() == format.file_name())
+            {
+                bail!("more than one --traj-format writes {}", format.file_name());
+            }
+        }
+
+        let integrator = Integrator::new(args.integrator, args.friction);
+        if args.respa_ratio == 0 {
+            bail!("--respa-ratio needs at least one inner step");
+        }
+        if args.schedule.is_some() && args.thermostat.is_none() && !integrator.is_stochastic() {
+            bail!("a --schedule needs a --thermostat or a Langevin integrator");
+        }
+
+        let simulation = Simulation {
             atoms,
             time_step,
             num_steps,
             step_num: 0,
+            elapsed: Time::default(),
             pot_energy: 0.0,
+            restraint_energy: 0.0,
+            wall_energy: 0.0,
+            bias_energy: 0.0,
             kin_energy: 0.0,
             tot_energy: 0.0,
-        })
+            init_energy: 0.0,
+            report,
+            traj_formats: args.traj_format.clone(),
+            energy_unit: args.energy_unit,
+            energy_format: args.energy_format,
+            bath_energy: 0.0,
+            watchdog: Watchdog::new(args),
+            momentum_every: args.remove_momentum,
+            thermostat: args.thermostat.map(|x| Thermostat::new(x, args.tau)),
+            schedule: args.schedule.clone(),
+            quench: false,
+            gaussian_config: default_gaussian_config(),
+            integrator,
+            respa: args
+                .cheap_config
+                .clone()
+                .map(|x| Respa::new(x, args.respa_ratio)),
+            adaptive: AdaptiveStep::new(args, args.time_step),
+            constraints,
+            restraints,
+            colvars,
+            touching: vec![],
+            logger,
+        };
+        simulation.check_settings()?;
+        Ok(simulation)
     }
 
-    pub fn run(mut self) {
+    //combinations of settings the steps cannot carry out, checked
+    //whenever a run or a stage sets them up.
+    fn check_settings(&self) -> Result<()> {
+        let history = matches!(
+            self.integrator,
+            Integrator::Leapfrog(_) | Integrator::Beeman(_)
+        );
+        if self.respa.is_some() && self.integrator != Integrator::default() {
+            bail!(
+                "multiple time stepping runs on velocity Verlet, the integrator cannot be changed"
+            );
+        }
+        if self.adaptive.is_some() && history {
+            bail!("leapfrog and Beeman keep a history that needs a fixed time step");
+        }
+        if self.restraints.metadynamics.is_some() && self.respa.is_some() {
+            bail!("metadynamics cannot be combined with multiple time stepping");
+        }
+        if !self.constraints.is_empty() && (history || self.respa.is_some()) {
+            bail!("constraints are applied to velocity Verlet and BAOAB steps only");
+        }
+        Ok(())
+    }
+
+    ///run the remaining steps, returning the final state. Fails when
+    ///the watchdog stops the run.
+    pub fn run(mut self) -> Result<Simulation> {
+        self.logger.start();
         if self.step_num == 0 {
-            Self::init_files();
-            self.report_trajectory();
-            self.report_energy();
-            self.report_velocity();
-            self.report_kinetic();
+            self.init_files();
+            self.report();
             self.save();
             self.step_num += 1;
+        } else {
+            self.traj_formats.iter().for_each(TrajFormat::reopen);
         }
+        let mut retries = 0;
         while self.step_num <= self.num_steps {
-            self.update_pos();
-            self.generate_input();
-            self.run_gaussian();
-            let data = self.read_gaussian();
-            self.update_next_forces(data.forces)
-                .update_vel()
-                .update_pot(data.scf)
+            let step_start = Instant::now();
+            if let Some(adaptive) = &self.adaptive {
+                self.time_step = adaptive.choose(&self.atoms);
+            }
+            let previous = self.clone();
+            self.move_spring();
+            let gaussian_start = Instant::now();
+            let data = match self.respa.is_some() {
+                true => self.respa_step(),
+                false => self.integrate_step(),
+            };
+            let gaussian_wall = gaussian_start.elapsed().as_secs_f64();
+            self.elapsed = self.elapsed + self.time_step;
+            self.remove_momentum()
+                .apply_thermostat()
+                .quench_velocities()
+                .update_pot(data.energy)
+                .update_restraint(data.restraint)
+                .update_wall(data.wall)
+                .update_bias(data.bias)
                 .update_kin()
                 .update_tot();
-            self.report_trajectory();
-            self.report_energy();
+            if !self.accept_step(previous.conserved_energy()) {
+                let adaptive = self.adaptive.take();
+                self.logger.debug(&format!(
+                    "Step {} redone, energy error too large for a time step of {} fs",
+                    self.step_num,
+                    self.time_step.as_fs()
+                ));
+                self = previous;
+                self.adaptive = adaptive;
+                continue;
+            }
+            if let Some(reason) = self.watch(previous.conserved_energy()) {
+                let path = self.write_diagnostic(&reason);
+                self.logger.warn(&format!(
+                    "Watchdog tripped at step {}: {}, snapshot written to {}",
+                    self.step_num, reason, path
+                ));
+                if self.watchdog.action == TripAction::Rollback
+                    && retries < self.watchdog.max_retries
+                {
+                    retries += 1;
+                    let time_step = Time::fs(self.time_step.as_fs() / 2.0);
+                    self = previous;
+                    self.time_step = time_step;
+                    self.integrator.reset_history();
+                    //an adaptive step is chosen anew, cap it instead
+                    if let Some(adaptive) = &mut self.adaptive {
+                        adaptive.limit(time_step);
+                    }
+                    self.logger.warn(&format!(
+                        "Rolled back step {}, retry {} of {} with a time step of {} fs",
+                        self.step_num,
+                        retries,
+                        self.watchdog.max_retries,
+                        time_step.as_fs()
+                    ));
+                    continue;
+                }
+                self.traj_formats.iter().for_each(TrajFormat::finish);
+                self.logger.warn("Simulation stopped by the watchdog");
+                bail!(
+                    "simulation stopped by the watchdog at step {}",
+                    self.step_num
+                );
+            }
+            retries = 0;
+            self.log_wall_touches();
+            self.deposit_hill();
+            self.report();
             self.save();
+            let unit = self.energy_unit;
+            self.logger.step(&StepRecord {
+                step: self.step_num,
+                num_steps: self.num_steps,
+                time: self.time(),
+                gaussian_wall,
+                step_wall: step_start.elapsed().as_secs_f64(),
+                scf_cycles: data.cycles,
+                pot_energy: unit.convert(self.pot_energy),
+                kin_energy: unit.convert(self.kin_energy),
+                tot_energy: unit.convert(self.tot_energy),
+                drift: unit.convert(self.drift()),
+                energy_unit: unit.label(),
+            });
             self.step_num += 1;
         }
+        self.traj_formats.iter().for_each(TrajFormat::finish);
+        self.logger.info("Simulation complete");
+        Ok(self)
     }
 
     pub fn init_forces(mut self) -> Self {
-        self.generate_input();
-        self.run_gaussian();
-        let data = self.read_gaussian();
-        self.update_forces(data.forces)
-            .update_pot(data.scf)
-            .update_kin()
-            .scale_temp()
-            .update_kin()
-            .update_tot()
+        if self.removes_momentum() {
+            momentum::remove(&mut self.atoms);
+        }
+        //a schedule starting at 0 K starts the atoms at rest
+        let temperature = match &self.schedule {
+            Some(schedule) => schedule.temperature(0, self.num_steps),
+            None => 300.0,
+        };
+        self.constrain_velocities();
+        self.update_kin().scale_temp(temperature);
+        self.initial_forces()
     }
 
-    pub fn from_save() -> Simulation {
-        let mut simulation: Simulation = Self::read_to_vec("save.json").last().unwrap().clone();
-        simulation.step_num += 1;
-        simulation
+    ///set up the next stage of a protocol, continuing from the current state.
+    pub fn begin_stage(
+        mut self,
+        stage: &Stage,
+        index: usize,
+        args: &Args,
+        root: &Path,
+    ) -> Result<Self> {
+        self.logger
+            .info(&format!("Starting stage {}: {}", index + 1, stage.name));
+        self.time_step = Time::fs(stage.time_step.unwrap_or(args.time_step));
+        self.adaptive = AdaptiveStep::new(args, self.time_step.as_fs());
+        self.num_steps = stage.num_steps;
+        self.step_num = 0;
+        self.elapsed = Time::default();
+        self.bath_energy = 0.0;
+        self.report = stage.report.unwrap_or_else(|| ReportIntervals::new(args));
+        self.thermostat = stage.thermostat;
+        self.integrator = stage
+            .integrator
+            .clone()
+            .unwrap_or_else(|| Integrator::new(args.integrator, args.friction));
+        self.schedule = stage.schedule();
+        self.quench = stage.kind == StageKind::Minimize;
+        let config = stage.config.as_deref().unwrap_or("config.yaml");
+        self.gaussian_config = root.join(config).to_string_lossy().into_owned();
+        self.respa = match (&stage.respa, &args.cheap_config) {
+            (Some(respa), _) => Some(Respa::new(
+                root.join(&respa.config).to_string_lossy().into_owned(),
+                respa.ratio,
+            )),
+            (None, Some(cheap)) => Some(Respa::new(
+                root.join(cheap).to_string_lossy().into_owned(),
+                args.respa_ratio,
+            )),
+            (None, None) => None,
+        };
+        self.check_settings()
+            .with_context(|| format!("in stage {}", stage.name))?;
+
+        if let Some(definitions) = &stage.restraints {
+            self.restraints.internal =
+                InternalRestraint::convert(definitions, args.energy_unit, self.atoms.len())
+                    .with_context(|| format!("in stage {}", stage.name))?;
+        }
+
+        if let Some(temperature) = stage.velocities {
+            atom::draw_velocities(&mut self.atoms, temperature);
+            self.constrain_velocities();
+            self.update_kin().scale_temp(temperature);
+        }
+        if self.quench {
+            for atom in self.atoms.iter_mut() {
+                atom.vel = Velocity::new(0.0, 0.0, 0.0);
+            }
+        }
+        Ok(self.initial_forces())
     }
 
-    fn freeze_atoms(atoms: &Vec<Atom>, string: String) -> Vec<Atom> {
-        let to_freeze = Self::parse_string(string);
-        let mut atoms = atoms.clone();
-        for value in to_freeze {
-            atoms[(value - 1) as usize].can_mv = false;
-            atoms[(value - 1) as usize].vel = Velocity::new(0.0, 0.0, 0.0);
+    ///set up a window of umbrella sampling, continuing from the current state.
+    pub fn begin_window(mut self, umbrella: Umbrella, index: usize, root: &Path) -> Self {
+        self.logger.info(&format!(
+            "Starting window {}: {}",
+            index + 1,
+            umbrella.describe(&self.atoms)
+        ));
+        self.step_num = 0;
+        self.elapsed = Time::default();
+        self.bath_energy = 0.0;
+        self.gaussian_config = root
+            .join(&self.gaussian_config)
+            .to_string_lossy()
+            .into_owned();
+        if let Some(respa) = &mut self.respa {
+            respa.config = root.join(&respa.config).to_string_lossy().into_owned();
         }
-        atoms
+        self.restraints.umbrella = Some(umbrella);
+        self.init_forces()
     }
 
-    fn validate_string(_string: String) -> Result<()> {
-        Ok(())
+    pub fn atoms(&self) -> &[Atom] {
+        &self.atoms
     }
 
-    fn parse_string(string: String) -> Vec<u32> {
-        let ranges = string
-            .split(',')
-            .map(|x| Self::convert_to_range(x.to_string()).gen_numbers())
-            .flatten()
-            .collect::<Vec<u32>>();
-        ranges
+    //current values and targets of the internal coordinate restraints.
+    fn log_restraints(&self) {
+        for (index, restraint) in self.restraints.internal.iter().enumerate() {
+            let (scale, label) = match restraint.kind {
+                Coordinate::Distance => (1.0, "A"),
+                _ => (180.0 / std::f64::consts::PI, "deg"),
+            };
+            self.logger.info(&format!(
+                "Restraint {}: {:?} of atoms {:?} is {:.3} {label}, target {:.3} {label}",
+                index + 1,
+                restraint.kind,
+                restraint
+                    .atoms
+                    .iter()
+                    .map(|x| x + 1)
+                    .collect::<Vec<usize>>(),
+                restraint.value(&self.atoms) * scale,
+                restraint.target * scale,
+            ));
+        }
     }
 
-    fn convert_to_range(line: String) -> Range {
-        let result = line
-            .split("-")
-            .into_iter()
-            .filter_map(|x| x.parse::<u32>().ok())
-            .collect::<Vec<u32>>();
-        Range::new(result[0], result[1])
+    //forces and energies of the starting geometry, the reference for the drift.
+    fn initial_forces(mut self) -> Self {
+        self.log_restraints();
+        self.logger.info("Computing initial forces");
+        let data = self.compute_forces();
+        let mut simulation = self
+            .update_forces(data.forces)
+            .update_pot(data.energy)
+            .update_restraint(data.restraint)
+            .update_wall(data.wall)
+            .update_bias(data.bias)
+            .update_kin()
+            .update_tot();
+        simulation.init_energy = simulation.tot_energy;
+        simulation
+    }
+
+    pub fn from_save(args: &Args) -> Result<Simulation> {
+        let mut simulation: Simulation = Self::read_to_vec("save.json").last().unwrap().clone();
+        //checkpoints written before the time step could change
+        if simulation.elapsed == Time::default() {
+            simulation.elapsed =
+                Time::fs(simulation.step_num as f64 * simulation.time_step.as_fs());
+        }
+        //hills of the last saved step, leaving out any written after it
+        let until = simulation.time() + 0.5 * simulation.time_step.as_fs();
+        if let Some(metadynamics) = &mut simulation.restraints.metadynamics {
+            metadynamics.load_hills("HILLS", simulation.energy_unit, until)?;
+        }
+        simulation.step_num += 1;
+        simulation.logger = Logger::new(args.verbosity, args.log_file.clone());
+        simulation.logger.info(&format!(
+            "Restarting from save.json at step {}",
+            simulation.step_num
+        ));
+        Ok(simulation)
+    }
+
+    fn freeze_atoms(atoms: &mut [Atom], indices: &[usize]) {
+        for &index in indices {
+            atoms[index].can_mv = false;
+            atoms[index].vel = Velocity::new(0.0, 0.0, 0.0);
+        }
     }
 
     fn read_to_vec<P>(path: P) -> Vec<Simulation>
@@ -133,239 +589,509 @@ impl Simulation {
         P: AsRef<Path>,
     {
         let file = File::open(path).expect("failed to open save.json");
-        let result: Vec<Simulation> = BufReader::new(file)
+        BufReader::new(file)
             .lines()
-            .into_iter()
             .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
-            .collect();
-        result
+            .collect()
     }
 
     fn save(&self) {
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open("save.json")
             .expect("failed to open save.json during report");
         let report =
             serde_json::to_string(&self).expect("unable to convert simulation into string");
-        write!(file, "{}\n", report).expect("failed to write to save.json");
+        writeln!(file, "{}", report).expect("failed to write to save.json");
     }
 
-    fn update_pos(&mut self) -> &mut Self {
-        let function = |x: Atom| -> Position<f64> {
-            if x.can_mv {
-                x.pos
-                    + (x.vel * self.time_step)
-                    + (0.5 * x.force * (1.0 / x.mass) * self.time_step.powi(2))
-            } else {
-                x.pos
-            }
-        };
-        let result = self
-            .atoms
-            .clone()
-            .into_iter()
-            .map(function)
-            .collect::<Vec<Position<f64>>>();
+    fn step_context(&self) -> StepContext {
+        StepContext {
+            time_step: self.time_step,
+            temperature: self.target_temperature(),
+        }
+    }
 
-        let mut index = 0;
-        for value in result {
-            self.atoms[index].pos = value;
-            index += 1;
+    //false when the change of the conserved energy over the step asks
+    //for it to be redone with a shorter time step.
+    fn accept_step(&mut self, previous_energy: f64) -> bool {
+        let error = (self.conserved_energy() - previous_energy).abs();
+        match &mut self.adaptive {
+            Some(adaptive) => adaptive.accept(error, self.time_step),
+            None => true,
         }
+    }
 
-        self
+    //a step of the integrator around a single force evaluation.
+    fn integrate_step(&mut self) -> ForceOutput {
+        let old: Vec<Position<f64>> = self.atoms.iter().map(|x| x.pos).collect();
+        self.advance();
+        if !self.constraints.is_empty()
+            && !self
+                .constraints
+                .shake(&mut self.atoms, &old, self.time_step)
+        {
+            self.logger.warn("SHAKE did not converge");
+        }
+        self.constraints
+            .restrict_positions(&mut self.atoms, &old, self.time_step);
+        let mut data = self.compute_forces();
+        self.update_next_forces(std::mem::take(&mut data.forces))
+            .finish_step();
+        if !self.constraints.is_empty() && !self.constraints.rattle(&mut self.atoms, self.time_step)
+        {
+            self.logger.warn("RATTLE did not converge");
+        }
+        self.constraints.restrict_velocities(&mut self.atoms);
+        data
     }
 
-    fn update_vel(&mut self) -> &mut Self {
-        let function = |x: Atom| -> Velocity<f64> {
-            if x.can_mv {
-                x.vel + (0.5 * (x.force + x.next_force) * (1.0 / x.mass) * self.time_step)
-            } else {
-                x.vel
+    //remove velocity components along the constrained bonds and fixes
+    //before the first step, the forces of these corrections are not reported.
+    fn constrain_velocities(&mut self) {
+        if !self.constraints.is_empty() {
+            self.constraints.rattle(&mut self.atoms, self.time_step);
+            for bond in self.constraints.bonds.iter_mut() {
+                bond.force = 0.0;
             }
-        };
-
-        let result = self
-            .atoms
-            .clone()
-            .into_iter()
-            .map(function)
-            .collect::<Vec<Velocity<f64>>>();
-
-        let mut index = 0;
-        for value in result {
-            self.atoms[index].vel = value;
-            self.atoms[index].force = self.atoms[index].next_force;
-            index += 1;
+            self.constraints.restrict_velocities(&mut self.atoms);
         }
-        self
     }
 
-    fn generate_input(&self) {
-        let input = OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .read(true)
-            .write(true)
-            .open("input.com")
-            .expect("failed to spawn input.com file");
-
-        let coords = self.clone().gen_coords();
-
-        let config = File::open("config.yaml").expect("failed to open config.yaml");
-        let interface = Gaussian::new(config)
-            .expect("failed to generate Gaussian16 interface. Check config.yaml");
-
-        interface.gen_input(&input).expect("failed to write input");
-        writeln!(&input, "\n{}\n", coords).expect("failed to write atomic coords");
+    //an outer multiple time step, the cheap forces driving the inner steps.
+    fn respa_step(&mut self) -> ForceOutput {
+        let mut respa = self
+            .respa
+            .take()
+            .expect("multiple time stepping is not set up");
+        let expensive = GaussianProvider::new(&self.gaussian_config, "input.com", "forces.out");
+        let expensive = Restrained::new(expensive, &self.restraints);
+        let config = respa.config.clone();
+        let cheap = GaussianProvider::new(&config, "cheap.com", "cheap.out");
+        let cheap = Restrained::new(cheap, &self.restraints);
+        let data = respa.step(&mut self.atoms, self.time_step, &cheap, &expensive);
+        self.respa = Some(respa);
+        data
     }
 
-    fn gen_coords(self) -> String {
-        let lines = self
-            .atoms
-            .into_iter()
-            .map(|x| {
-                format!(
-                    "{} {:.5} {:.5} {:.5}",
-                    x.symbol,
-                    x.pos.as_vec().x,
-                    x.pos.as_vec().y,
-                    x.pos.as_vec().z
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
-        lines
+    //move the atoms ahead of the force evaluation.
+    fn advance(&mut self) -> &mut Self {
+        let step = self.step_context();
+        self.bath_energy += self.integrator.scheme().advance(&mut self.atoms, &step);
+        self
     }
 
-    fn run_gaussian(&self) {
-        let input = File::open("input.com").expect("failed to open input.com for Gaussian16 run");
-        let output = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .truncate(true)
-            .open("forces.out")
-            .expect("failed to create output file");
-
-        let config = File::open("config.yaml").expect("failed to open config.yaml");
-        let interface = Gaussian::new(config)
-            .expect("failed to generate Gaussian16 interface. Check config.yaml");
-
-        interface
-            .run(input, output)
-            .expect("Gaussian16 calculation failed");
+    //complete the velocities with the new forces, which become the current ones.
+    fn finish_step(&mut self) -> &mut Self {
+        let step = self.step_context();
+        self.integrator.scheme().finish(&mut self.atoms, &step);
+        for atom in self.atoms.iter_mut() {
+            atom.force = atom.next_force;
+        }
+        self
     }
 
-    fn read_gaussian(&self) -> GaussianOutput {
-        let output = File::open("forces.out").expect("failed to open forces.out");
-        GaussianOutput::new(output)
+    //energy and forces from the Gaussian16 route of the simulation.
+    fn compute_forces(&self) -> ForceOutput {
+        let gaussian = GaussianProvider::new(&self.gaussian_config, "input.com", "forces.out");
+        Restrained::new(gaussian, &self.restraints).compute(&self.atoms)
     }
 
     fn update_forces(&mut self, forces: Vec<Force<f64>>) -> &mut Self {
-        let mut index = 0;
-        for force in forces {
+        for (index, force) in forces.into_iter().enumerate() {
             self.atoms[index].force = force;
-            index += 1;
         }
         self
     }
 
     fn update_next_forces(&mut self, forces: Vec<Force<f64>>) -> &mut Self {
-        let mut index = 0;
-        for force in forces {
+        for (index, force) in forces.into_iter().enumerate() {
             self.atoms[index].next_force = force;
-            index += 1;
         }
         self
     }
 
+    //potential energy in kJ/mol
     fn update_pot(&mut self, value: f64) -> &mut Self {
-        self.pot_energy = (value * 2625.5) / 100.0;
+        self.pot_energy = value;
+        self
+    }
+
+    //restraint energy in kJ/mol
+    fn update_restraint(&mut self, value: f64) -> &mut Self {
+        self.restraint_energy = value;
+        self
+    }
+
+    //wall energy in kJ/mol
+    fn update_wall(&mut self, value: f64) -> &mut Self {
+        self.wall_energy = value;
         self
     }
 
+    //metadynamics bias energy in kJ/mol
+    fn update_bias(&mut self, value: f64) -> &mut Self {
+        self.bias_energy = value;
+        self
+    }
+
+    //deposit a hill when one is due. The rise of the bias is accounted to
+    //the bath and its forces are added to the current ones, as they would
+    //have been computed with the hill in place.
+    fn deposit_hill(&mut self) {
+        let time = self.time();
+        let unit = self.energy_unit;
+        let metadynamics = match &mut self.restraints.metadynamics {
+            Some(x) if x.due(self.step_num) => x,
+            _ => return,
+        };
+        let zero = Force::new(0.0, 0.0, 0.0);
+        let mut before = vec![zero; self.atoms.len()];
+        let mut after = vec![zero; self.atoms.len()];
+        let previous = metadynamics.apply(&self.atoms, &mut before);
+        let hill = metadynamics.deposit(&self.atoms, time);
+        let line = metadynamics.hills_line(&hill, unit);
+        let bias = metadynamics.apply(&self.atoms, &mut after);
+        for ((atom, before), after) in self.atoms.iter_mut().zip(before).zip(after) {
+            atom.force = atom.force + (after - before);
+            atom.next_force = atom.force;
+        }
+        self.bias_energy = bias;
+        self.bath_energy -= bias - previous;
+        self.update_tot();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open("HILLS")
+            .expect("failed to report hills");
+        file.write_all(line.as_bytes())
+            .expect("failed to report hills");
+    }
+
+    //move the pulling spring ahead over the step, the work it does on the
+    //atoms is accounted to the bath like the energy of a thermostat.
+    fn move_spring(&mut self) {
+        if let Some(steering) = &mut self.restraints.steering {
+            self.bath_energy -= steering.advance(&self.atoms, self.time_step);
+        }
+    }
+
+    //log the atoms that reached the wall during the step.
+    fn log_wall_touches(&mut self) {
+        let wall = match &self.restraints.wall {
+            Some(x) => x,
+            None => return,
+        };
+        let touching = wall.touching(&self.atoms);
+        for (index, atom) in self.atoms.iter().enumerate() {
+            if touching[index] && !self.touching.get(index).copied().unwrap_or(false) {
+                self.logger.info(&format!(
+                    "Step {}: atom {} ({}) touched the wall",
+                    self.step_num,
+                    index + 1,
+                    atom.symbol
+                ));
+            }
+        }
+        self.touching = touching;
+    }
+
     fn update_kin(&mut self) -> &mut Self {
         let value: f64 = self
             .atoms
             .clone()
             .into_iter()
-            .map(|x| 0.5 * x.mass * x.vel.sqr_norm())
+            .map(|x| 0.5 * x.mass.as_amu() * x.vel.sqr_norm())
             .sum();
-        self.kin_energy = value * 100.0;
+        self.kin_energy = value * units::MD_ENERGY_KJ_MOL;
         self
     }
 
     fn update_tot(&mut self) -> Self {
-        let value = self.pot_energy + self.kin_energy;
+        let value = self.pot_energy
+            + self.restraint_energy
+            + self.wall_energy
+            + self.bias_energy
+            + self.kin_energy;
         self.tot_energy = value;
         self.clone()
     }
 
-    fn init_files() {
-        InitFiles::new()
+    fn init_files(&self) {
+        InitFiles::init(self.energy_unit, self.energy_format);
+        if !self.constraints.bonds.is_empty() {
+            InitFiles::init_constraints(self.energy_unit);
+        }
+        if !self.colvars.is_empty() {
+            InitFiles::init_colvars(&self.colvars);
+        }
+        if let Some(metadynamics) = &self.restraints.metadynamics {
+            InitFiles::generate("HILLS", metadynamics.hills_file(self.energy_unit));
+        }
+        if let Some(umbrella) = &self.restraints.umbrella {
+            InitFiles::generate("umbrella.txt", umbrella.header(self.energy_unit));
+        }
+        if let Some(steering) = &self.restraints.steering {
+            InitFiles::generate("steered.txt", steering.header(self.energy_unit));
+        }
+        for format in &self.traj_formats {
+            format.init(&self.atoms, self.time_step.as_fs(), self.report.trajectory);
+        }
+    }
+
+    fn scale_temp(&mut self, target: f64) -> &mut Self {
+        let temperature = self.temperature();
+        if temperature == 0.0 {
+            return self;
+        }
+        let scalar = (target / temperature).sqrt();
+        for atom in self.atoms.iter_mut() {
+            atom.vel = atom.vel * scalar;
+        }
+        self
+    }
+
+    fn time(&self) -> f64 {
+        self.elapsed.as_fs()
+    }
+
+    //check the guards against the state after a step.
+    fn watch(&self, previous_energy: f64) -> Option<String> {
+        let observation = Observation {
+            atoms: &self.atoms,
+            drift: self.drift(),
+            jump: self.conserved_energy() - previous_energy,
+            temperature: self.temperature(),
+        };
+        self.watchdog.check(&observation, self.energy_unit)
+    }
+
+    //full state of the simulation at the step a guard tripped, returns the file name.
+    fn write_diagnostic(&self, reason: &str) -> String {
+        #[derive(Serialize)]
+        struct Diagnostic<'a> {
+            reason: &'a str,
+            temperature: f64,
+            simulation: &'a Simulation,
+        }
+        let path = format!("diagnostic_{}.json", self.step_num);
+        let diagnostic = Diagnostic {
+            reason,
+            temperature: self.temperature(),
+            simulation: self,
+        };
+        let to_write =
+            serde_json::to_string_pretty(&diagnostic).expect("unable to convert diagnostic");
+        std::fs::write(&path, to_write).expect("failed to write diagnostic snapshot");
+        path
     }
 
-    fn scale_temp(&mut self) -> &mut Self {
-        let scalar = (300.0
-            / ((2.0 / 3.0) * ((self.kin_energy * 100.0 * 1000.0) / 8.31446261815324)))
-            .sqrt();
-        let mut index = 0;
-        let length = self.atoms.clone();
-        for _atom in length {
-            self.atoms[index].vel = self.atoms[index].vel * scalar;
-            index += 1;
+    fn report(&self) {
+        let step = self.step_num;
+        if !self.colvars.is_empty() {
+            self.report_colvars();
+        }
+        if let Some(umbrella) = &self.restraints.umbrella {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open("umbrella.txt")
+                .expect("failed to report umbrella");
+            file.write_all(umbrella.line(self.time(), &self.atoms).as_bytes())
+                .expect("failed to report umbrella");
+        }
+        if let Some(steering) = &self.restraints.steering {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open("steered.txt")
+                .expect("failed to report work");
+            let line = steering.line(self.time(), &self.atoms, self.energy_unit);
+            file.write_all(line.as_bytes())
+                .expect("failed to report work");
+        }
+        if ReportIntervals::due(self.report.trajectory, step) {
+            self.report_trajectory();
+        }
+        if ReportIntervals::due(self.report.energy, step) {
+            self.report_energy();
         }
+        if ReportIntervals::due(self.report.velocity, step) {
+            self.report_velocity();
+        }
+        if ReportIntervals::due(self.report.kinetic, step) {
+            self.report_kinetic();
+        }
+        if ReportIntervals::due(self.report.force, step) {
+            self.report_force();
+            if !self.constraints.bonds.is_empty() {
+                self.report_constraints();
+            }
+        }
+    }
+
+    //frozen atoms, fixed axes and centroids, position restraints and a
+    //wall with a fixed centre tie the system to points in space.
+    fn anchored(&self) -> bool {
+        self.atoms.iter().any(|x| !x.can_mv)
+            || self.constraints.anchors()
+            || self.restraints.anchors()
+    }
+
+    //momentum is only removed while nothing anchors the system. Anchors
+    //change the momentum of the mobile atoms for real, as for a molecule
+    //drifting over a frozen surface, so removing it, even only the linear
+    //part over the mobile atoms, would take out physical motion rather
+    //than numerical noise.
+    fn removes_momentum(&self) -> bool {
+        self.momentum_every != 0 && !self.anchored()
+    }
+
+    //take the overall translation and rotation out of the velocities,
+    //the kinetic energy removed is accounted to the bath.
+    fn remove_momentum(&mut self) -> &mut Self {
+        if !self.removes_momentum() || !self.step_num.is_multiple_of(self.momentum_every) {
+            return self;
+        }
+        let before = self.update_kin().kin_energy;
+        momentum::remove(&mut self.atoms);
+        let after = self.update_kin().kin_energy;
+        self.bath_energy += before - after;
         self
     }
 
+    //thermostat target of the current step in K.
+    fn target_temperature(&self) -> f64 {
+        match &self.schedule {
+            Some(schedule) => schedule.temperature(self.step_num, self.num_steps),
+            None => 0.0,
+        }
+    }
+
+    //rescale the velocities towards the target temperature, the kinetic
+    //energy exchanged is accounted to the bath.
+    fn apply_thermostat(&mut self) -> &mut Self {
+        let thermostat = match self.thermostat {
+            Some(x) => x,
+            None => return self,
+        };
+        let dof = self.degrees_of_freedom();
+        let target = 0.5 * dof as f64 * units::kt(self.target_temperature());
+        let before = self.update_kin().kin_energy;
+        let scalar = thermostat.scale(before, target, dof, self.time_step.as_fs());
+        for atom in self.atoms.iter_mut() {
+            atom.vel = atom.vel * scalar;
+        }
+        let after = self.update_kin().kin_energy;
+        self.bath_energy += before - after;
+        self
+    }
+
+    //velocity quench: atoms moving against the force on them are stopped.
+    fn quench_velocities(&mut self) -> &mut Self {
+        if !self.quench {
+            return self;
+        }
+        let before = self.update_kin().kin_energy;
+        for atom in self.atoms.iter_mut() {
+            if atom.vel.as_vec().dot(atom.force.as_vec()) < 0.0 {
+                atom.vel = Velocity::new(0.0, 0.0, 0.0);
+            }
+        }
+        let after = self.update_kin().kin_energy;
+        self.bath_energy += before - after;
+        self
+    }
+
+    //the centre of mass velocity is removed when the atoms are generated
+    //and stays zero as long as nothing anchors the system, which takes
+    //three translational degrees of freedom out. Periodic removal of the
+    //angular momentum takes out the rotational ones as well.
+    fn degrees_of_freedom(&self) -> usize {
+        let mobile = self.atoms.iter().filter(|x| x.can_mv).count();
+        let mut constrained = match self.anchored() {
+            true => 0,
+            false => 3,
+        };
+        if self.removes_momentum() {
+            constrained += momentum::rotational_dof(&self.atoms);
+        }
+        constrained += self.constraints.removed_dof(&self.atoms);
+        (3 * mobile).saturating_sub(constrained)
+    }
+
+    //instantaneous temperature in K from the kinetic energy of the mobile atoms.
+    fn temperature(&self) -> f64 {
+        let dof = self.degrees_of_freedom();
+        if dof == 0 {
+            return 0.0;
+        }
+        (2.0 * self.kin_energy) / (dof as f64 * units::kt(1.0))
+    }
+
+    //total energy plus the energy exchanged with the thermostat, kJ/mol
+    fn conserved_energy(&self) -> f64 {
+        self.tot_energy + self.bath_energy
+    }
+
+    //change of the conserved energy since step 0, kJ/mol
+    fn drift(&self) -> f64 {
+        self.conserved_energy() - self.init_energy
+    }
+
     fn report_trajectory(&self) {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("trajectory.xyz")
-            .expect("failed to report trajectory");
-        let to_write = format!(
-            "{}\ntrjectory\n{}\n",
-            self.atoms.len(),
-            self.clone().gen_coords()
-        );
-        file.write(to_write.as_bytes())
-            .expect("you managed the imposable");
+        let frame = Frame {
+            step: self.step_num,
+            //written on every multiple of the interval from step 0
+            index: self.step_num / self.report.trajectory,
+            time: self.time(),
+            atoms: &self.atoms,
+            pot_energy: self.pot_energy,
+            kin_energy: self.kin_energy,
+            tot_energy: self.tot_energy,
+            temperature: self.temperature(),
+            time_step: self.time_step.as_fs(),
+            unit: self.energy_unit,
+        };
+        for format in &self.traj_formats {
+            format.write(&frame);
+        }
     }
 
     fn report_energy(&self) {
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
-            .open("energy.txt")
+            .open(self.energy_format.file_name())
             .expect("failed to report energy");
-        let to_write = format!(
-            "{:<30.2} {:<30.6} {:<30.6} {:.6}\n",
-            self.step_num as f64 * self.time_step,
-            self.pot_energy,
-            self.kin_energy,
-            self.tot_energy
-        );
-        file.write(to_write.as_bytes())
+        let unit = self.energy_unit;
+        let row = EnergyRow {
+            step: self.step_num,
+            time: self.time(),
+            time_step: self.time_step.as_fs(),
+            potential: unit.convert(self.pot_energy),
+            restraint: unit.convert(self.restraint_energy),
+            wall: unit.convert(self.wall_energy),
+            bias: unit.convert(self.bias_energy),
+            kinetic: unit.convert(self.kin_energy),
+            total: unit.convert(self.tot_energy),
+            temperature: self.temperature(),
+            conserved: unit.convert(self.conserved_energy()),
+            drift: unit.convert(self.drift()),
+            unit: unit.label(),
+        };
+        let to_write = self.energy_format.row(&row);
+        file.write_all(to_write.as_bytes())
             .expect("you managed the imposable");
     }
 
     fn report_velocity(&self) {
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .open("velocity.txt")
             .expect("failed to report velocity");
-        let mut to_write: Vec<String> = vec![];
-        let mut index = 1;
-        for atom in self.atoms.clone() {
+        let mut to_write: Vec<String> = vec![frame_header(self.step_num, self.time())];
+        for (index, atom) in self.atoms.iter().enumerate() {
             let string = format!(
                 "{:<30} {:<30} {:<30} {:<30} {:<30} {}",
-                index,
+                index + 1,
                 atom.symbol,
                 atom.vel.as_vec().x,
                 atom.vel.as_vec().y,
@@ -373,78 +1099,166 @@ impl Simulation {
                 atom.vel.norm()
             );
             to_write.push(string);
-            index += 1;
         }
 
-        let value = to_write.join("\n");
-        file.write(value.as_bytes())
+        let value = to_write.join("\n") + "\n\n";
+        file.write_all(value.as_bytes())
             .expect("you managed the imposable");
     }
 
     fn report_kinetic(&self) {
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .open("kinetic.txt")
             .expect("failed to report kinetic");
-        let mut to_write: Vec<String> = vec![];
-        let mut index = 1;
-        for atom in self.atoms.clone() {
+        let mut to_write: Vec<String> = vec![frame_header(self.step_num, self.time())];
+        for (index, atom) in self.atoms.iter().enumerate() {
             let string = format!(
                 "{:<30} {:<30} {}",
-                index,
+                index + 1,
+                atom.symbol,
+                self.energy_unit
+                    .convert_md(0.5 * atom.mass.as_amu() * atom.vel.sqr_norm())
+            );
+            to_write.push(string);
+        }
+
+        let value = to_write.join("\n") + "\n\n";
+        file.write_all(value.as_bytes())
+            .expect("you managed the imposable");
+    }
+
+    fn report_force(&self) {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open("force.txt")
+            .expect("failed to report force");
+        let mut to_write: Vec<String> = vec![frame_header(self.step_num, self.time())];
+        for (index, atom) in self.atoms.iter().enumerate() {
+            let string = format!(
+                "{:<30} {:<30} {:<30} {:<30} {:<30} {}",
+                index + 1,
                 atom.symbol,
-                (0.5 * atom.mass * atom.vel.sqr_norm() * 100.0)
+                self.energy_unit.convert_md(atom.force.as_vec().x),
+                self.energy_unit.convert_md(atom.force.as_vec().y),
+                self.energy_unit.convert_md(atom.force.as_vec().z),
+                self.energy_unit.convert_md(atom.force.norm())
             );
             to_write.push(string);
-            index += 1;
         }
 
-        let value = to_write.join("\n");
-        file.write(value.as_bytes())
+        let value = to_write.join("\n") + "\n\n";
+        file.write_all(value.as_bytes())
             .expect("you managed the imposable");
     }
+
+    fn report_colvars(&self) {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open("colvar.txt")
+            .expect("failed to report collective variables");
+        let mut columns = vec![self.step_num.to_string(), format!("{:.2}", self.time())];
+        for colvar in &self.colvars {
+            let (scale, _) = colvar.display();
+            columns.push(format!("{:.6}", colvar.evaluate(&self.atoms).0 * scale));
+        }
+        file.write_all(report::columns(&columns).as_bytes())
+            .expect("failed to report collective variables");
+    }
+
+    fn report_constraints(&self) {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open("constraints.txt")
+            .expect("failed to report constraints");
+        let mut to_write: Vec<String> = vec![frame_header(self.step_num, self.time())];
+        for bond in &self.constraints.bonds {
+            let string = format!(
+                "{:<30} {:<30} {:<30} {}",
+                bond.i + 1,
+                bond.j + 1,
+                (self.atoms[bond.i].pos - self.atoms[bond.j].pos).norm(),
+                self.energy_unit.convert_md(bond.force)
+            );
+            to_write.push(string);
+        }
+
+        let value = to_write.join("\n") + "\n\n";
+        file.write_all(value.as_bytes())
+            .expect("failed to report constraints");
+    }
+}
+
+fn default_gaussian_config() -> String {
+    "config.yaml".to_string()
+}
+
+fn default_traj_formats() -> Vec<TrajFormat> {
+    vec![TrajFormat::default()]
 }
 
 struct InitFiles {}
 
 impl InitFiles {
-    fn new() {
-        Self::init_energy();
-        Self::init_kinetic();
+    fn init(unit: EnergyUnit, format: EnergyFormat) {
+        Self::generate(format.file_name(), format.header(unit));
+        Self::init_kinetic(unit);
         Self::init_velocity();
-        Self::init_trajectory();
+        Self::init_force(unit);
         Self::init_save();
     }
 
-    fn init_energy() {
+    fn init_kinetic(unit: EnergyUnit) {
         let init_string = format!(
-            "{:<30} {:<30} {:<30} {}\n",
-            "Time fs", "Potential 100 KJ/mol", "Kinetic 100 KJ/mol", "Total 100 KJ/mol"
+            "{:<30} {:<30} Kinetic {}\n",
+            "Number",
+            "Symbol",
+            unit.label()
         );
 
-        Self::generate("energy.txt", init_string);
+        Self::generate("kinetic.txt", init_string);
     }
 
-    fn init_kinetic() {
+    fn init_velocity() {
         let init_string = format!(
-            "{:<30} {:<30} {}\n",
-            "Number", "Symbol", "Kinetic 100 kJ/mol"
+            "{:<30} {:<30} {:<30} {:<30} {:<30} {}\n",
+            "Number", "Symbol", "X A/fs", "Y A/fs", "Z A/fs", "Magnitude A/fs"
         );
-
-        Self::generate("kinetic.txt", init_string);
+        Self::generate("velocity.txt", init_string);
     }
 
-    fn init_velocity() {
+    fn init_force(unit: EnergyUnit) {
+        let label = unit.force_label();
         let init_string = format!(
             "{:<30} {:<30} {:<30} {:<30} {:<30} {}\n",
-            "Number", "Symbol", "X", "Y", "Z", "Magnitude"
+            "Number",
+            "Symbol",
+            "X ".to_string() + &label,
+            "Y ".to_string() + &label,
+            "Z ".to_string() + &label,
+            "Magnitude ".to_string() + &label
         );
-        Self::generate("velocity.txt", init_string);
+        Self::generate("force.txt", init_string);
     }
 
-    fn init_trajectory() {
-        Self::generate("trajectory.xyz", "".to_string());
+    fn init_constraints(unit: EnergyUnit) {
+        let init_string = format!(
+            "{:<30} {:<30} {:<30} {}\n",
+            "I",
+            "J",
+            "Length A",
+            "Force ".to_string() + &unit.force_label()
+        );
+        Self::generate("constraints.txt", init_string);
+    }
+
+    fn init_colvars(colvars: &[Colvar]) {
+        let mut columns = vec!["Step".to_string(), "Time fs".to_string()];
+        for colvar in colvars {
+            let (_, unit) = colvar.display();
+            columns.push(format!("{} {}", colvar.name, unit).trim_end().to_string());
+        }
+        Self::generate("colvar.txt", report::columns(&columns));
     }
 
     fn init_save() {
@@ -456,77 +1270,8 @@ impl InitFiles {
             .create(true)
             .truncate(true)
             .write(true)
-            .open(&name)
-            .expect(format!("failed to init: {} file", name).as_str());
-        file.write(init_string.as_bytes()).unwrap();
-    }
-}
-
-struct GaussianOutput {
-    scf: f64,
-    forces: Vec<Force<f64>>,
-}
-
-impl GaussianOutput {
-    pub fn new(mut file: File) -> GaussianOutput {
-        let mut buffer = String::new();
-        let to_find = Regex::new(r"^(\s)+\d+(\s)+\d+((\s+)-?\d+.\d+){3}").unwrap();
-        let to_find_scf = Regex::new(r"^ SCF Done").unwrap();
-        file.read_to_string(&mut buffer).unwrap();
-
-        let forces = buffer
-            .clone()
-            .lines()
-            .filter(|x| to_find.is_match(x))
-            .map(|x| x.to_string())
-            .map(|x| Self::convert_to_force(x))
-            .collect::<Vec<Force<f64>>>();
-
-        let scf = buffer
-            .lines()
-            .filter(|x| to_find_scf.is_match(x))
-            .map(|x| x.to_string())
-            .rev()
-            .take(1)
-            .collect::<String>()
-            .split_whitespace()
-            .into_iter()
-            .find_map(|x| x.parse::<f64>().ok())
-            .unwrap();
-
-        GaussianOutput { scf, forces }
-    }
-
-    fn convert_to_force(line: String) -> Force<f64> {
-        let result = line
-            .split_whitespace()
-            .into_iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
-
-        let force = Force::new(
-            result[2].parse::<f64>().unwrap(),
-            result[3].parse::<f64>().unwrap(),
-            result[4].parse::<f64>().unwrap(),
-        );
-
-        //convert form Eh/Bohr to Ag/mol*fs^2
-        force * 0.496147792
-    }
-}
-
-struct Range {
-    low: u32,
-    high: u32,
-}
-
-impl Range {
-    fn new(low: u32, high: u32) -> Range {
-        Range { low, high }
-    }
-
-    fn gen_numbers(&self) -> Vec<u32> {
-        let result: Vec<u32> = (self.low..=self.high).collect();
-        result
+            .open(name)
+            .unwrap_or_else(|_| panic!("failed to init: {} file", name));
+        file.write_all(init_string.as_bytes()).unwrap();
     }
 }
@@ -0,0 +1,304 @@
+use anyhow::{bail, Context, Result};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::cli::Args;
+use crate::colvar::{Colvar, Harmonic};
+use crate::plumed::{self, Header};
+use crate::quantity::Time;
+use crate::report;
+use crate::units::{self, EnergyUnit};
+use crate::vectored::Force;
+
+///Steered MD: a harmonic spring k/2 (s - c)^2 on a collective variable
+///whose centre c moves at a constant rate from the starting value of the
+///variable. Moving the spring does work on the atoms, accumulated as the
+///change of the spring energy at fixed positions.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Steering {
+    #[serde(flatten)]
+    spring: Harmonic,
+    ///rate of the centre in internal units per fs
+    rate: f64,
+    ///work done by the spring so far in kJ/mol
+    work: f64,
+}
+
+impl Steering {
+    ///None unless a variable is pulled with `--steer`, the rate is given
+    ///in the units of colvar.txt per ps.
+    pub fn new(args: &Args, colvars: &[Colvar], atoms: &[Atom]) -> Result<Option<Steering>> {
+        let spec = match &args.steer {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let (name, rate) = spec
+            .rsplit_once(':')
+            .with_context(|| format!("invalid --steer \"{}\", expected NAME:RATE", spec))?;
+        let rate = rate
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|x| x.is_finite() && *x != 0.0)
+            .with_context(|| {
+                format!(
+                    "invalid pulling rate \"{}\", expected a nonzero number",
+                    rate
+                )
+            })?;
+        let colvar = colvars
+            .iter()
+            .find(|x| x.name == name)
+            .cloned()
+            .with_context(|| format!("unknown collective variable \"{}\"", name))?;
+        let force_constant = args
+            .steer_k
+            .filter(|x| *x > 0.0)
+            .context("--steer needs a positive --steer-k")?;
+        let scale = colvar.display().0;
+        Ok(Some(Steering {
+            spring: Harmonic {
+                centre: colvar.evaluate(atoms).0,
+                force_constant: force_constant / args.energy_unit.convert(1.0),
+                colvar,
+            },
+            rate: rate / scale / 1000.0,
+            work: 0.0,
+        }))
+    }
+
+    ///add the spring forces in amu A/fs^2 to `forces`, returning the
+    ///spring energy in kJ/mol.
+    pub fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        self.spring.apply(atoms, forces)
+    }
+
+    ///move the centre over a time step, returning the work done on the
+    ///atoms in kJ/mol.
+    pub fn advance(&mut self, atoms: &[Atom], time_step: Time) -> f64 {
+        let spring = &mut self.spring;
+        let value = spring.colvar.evaluate(atoms).0;
+        let centre = spring.centre + self.rate * time_step.as_fs();
+        let work = spring.energy(value, centre) - spring.energy(value, spring.centre);
+        spring.centre = centre;
+        self.work += work;
+        work
+    }
+
+    ///header of steered.txt, values in the units of colvar.txt.
+    pub fn header(&self, unit: EnergyUnit) -> String {
+        let colvar = &self.spring.colvar;
+        let (scale, _) = colvar.display();
+        let fields = ["time", "centre", &colvar.name, "force", "work"];
+        Header::new(fields.iter().map(|x| x.to_string()).collect())
+            .set(
+                "force_constant",
+                format!(
+                    "{:.8}",
+                    unit.convert(self.spring.force_constant) / (scale * scale)
+                ),
+            )
+            .set("rate", format!("{:.8}", self.rate * scale * 1000.0))
+            .set("energy_unit", unit.label())
+            .write()
+    }
+
+    ///line of steered.txt: the centre, the variable, the spring force along
+    ///it and the accumulated work.
+    pub fn line(&self, time: f64, atoms: &[Atom], unit: EnergyUnit) -> String {
+        let (scale, _) = self.spring.colvar.display();
+        let value = self.spring.colvar.evaluate(atoms).0;
+        format!(
+            "{:.2} {:.6} {:.6} {:.6} {:.8}\n",
+            time,
+            self.spring.centre * scale,
+            value * scale,
+            unit.convert(self.spring.force(value)) / scale,
+            unit.convert(self.work)
+        )
+    }
+
+    ///the starting value and rate as logged.
+    pub fn describe(&self) -> String {
+        let (scale, unit) = self.spring.colvar.display();
+        format!(
+            "Pulling {} from {:.3} {unit} at {:.4} {unit}/ps",
+            self.spring.colvar.name,
+            self.spring.centre * scale,
+            self.rate * scale * 1000.0
+        )
+    }
+}
+
+//work along the centre of the spring in one pulling run.
+struct Pull {
+    centres: Vec<f64>,
+    works: Vec<f64>,
+}
+
+impl Pull {
+    //work at a centre, interpolated linearly between the recorded steps.
+    fn work(&self, centre: f64) -> f64 {
+        let forward = self.centres.last() > self.centres.first();
+        let index = self
+            .centres
+            .partition_point(|x| if forward { *x < centre } else { *x > centre })
+            .clamp(1, self.centres.len() - 1);
+        let (c0, c1) = (self.centres[index - 1], self.centres[index]);
+        let (w0, w1) = (self.works[index - 1], self.works[index]);
+        if c1 == c0 {
+            return w1;
+        }
+        w0 + (w1 - w0) * (centre - c0) / (c1 - c0)
+    }
+}
+
+///Free energy along the pulled variable from several pulling runs with
+///Jarzynski's equality, dF = -kT ln <exp(-W / kT)>, Phys. Rev. Lett. 78,
+///2690 (1997). The work of every run is interpolated onto `points` centres
+///of the spring over the range all runs covered.
+pub fn jarzynski(paths: &[String], temperature: f64, points: usize, output: &str) -> Result<()> {
+    if points < 2 || temperature <= 0.0 {
+        bail!("the estimate needs at least two points and a positive temperature");
+    }
+    let mut name = String::new();
+    let mut unit = String::new();
+    let mut pulls = vec![];
+    for path in paths {
+        let (pull, header_name, header_unit) = read_pull(path)?;
+        if !name.is_empty() && (header_name != name || header_unit != unit) {
+            bail!(
+                "{} pulls {} in {}, not {} in {}",
+                path,
+                header_name,
+                header_unit,
+                name,
+                unit
+            );
+        }
+        (name, unit) = (header_name, header_unit);
+        pulls.push(pull);
+    }
+    if pulls.len() < 2 {
+        bail!("the Jarzynski estimate needs at least two pulling runs");
+    }
+    let energy_unit = EnergyUnit::value_variants()
+        .iter()
+        .find(|x| x.label() == unit)
+        .with_context(|| format!("unknown energy unit \"{}\"", unit))?;
+    let kt = energy_unit.convert(units::kt(temperature));
+
+    //range of the centre every run covered, in the direction of pulling
+    let start = pulls[0].centres[0];
+    let forward = pulls[0].centres.last().unwrap() > &start;
+    let end = pulls
+        .iter()
+        .map(|x| *x.centres.last().unwrap())
+        .reduce(|a, b| if forward { a.min(b) } else { a.max(b) })
+        .unwrap();
+    if pulls.iter().any(|x| (x.centres[0] - start).abs() > 1e-6) {
+        bail!("the runs start the spring at different centres");
+    }
+    if end == start || (end > start) != forward {
+        bail!("the runs do not pull the spring the same way");
+    }
+
+    let mut to_write = report::columns(&[
+        name,
+        format!("Free energy {}", unit),
+        format!("Mean work {}", unit),
+        format!("Work deviation {}", unit),
+    ]);
+    let count = pulls.len() as f64;
+    for i in 0..points {
+        let centre = start + (end - start) * i as f64 / (points - 1) as f64;
+        let works: Vec<f64> = pulls.iter().map(|x| x.work(centre)).collect();
+        let mean = works.iter().sum::<f64>() / count;
+        let deviation =
+            (works.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt();
+        //exponential average shifted by the smallest work against overflow
+        let least = works.iter().cloned().fold(f64::INFINITY, f64::min);
+        let sum: f64 = works.iter().map(|x| (-(x - least) / kt).exp()).sum();
+        let free_energy = least - kt * (sum / count).ln();
+        to_write.push_str(&format!(
+            "{:<30.6} {:<30.6} {:<30.6} {:.6}\n",
+            centre, free_energy, mean, deviation
+        ));
+    }
+    std::fs::write(output, to_write).with_context(|| format!("failed to write {}", output))?;
+    Ok(())
+}
+
+//the run of a steered.txt with the name of the variable and the energy unit.
+fn read_pull(path: &str) -> Result<(Pull, String, String)> {
+    let (header, rows) = plumed::read(path)?;
+    let name = match header.fields.as_slice() {
+        [time, centre, name, force, work]
+            if time == "time" && centre == "centre" && force == "force" && work == "work" =>
+        {
+            name.clone()
+        }
+        _ => bail!("{} is not a steered.txt", path),
+    };
+    if rows.len() < 2 {
+        bail!("{} holds fewer than two steps", path);
+    }
+    let pull = Pull {
+        centres: rows.iter().map(|x| x[1]).collect(),
+        works: rows.iter().map(|x| x[4]).collect(),
+    };
+    let unit = header.get("energy_unit").unwrap_or_default().to_string();
+    Ok((pull, name, unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //runs doing the same work give that work as the free energy.
+    #[test]
+    fn jarzynski_constant_work() {
+        let dir = std::env::temp_dir().join(format!("ezaimd_jarzynski_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let work = |centre: f64| 4.0 * (centre - 1.0);
+        let mut paths = vec![];
+        for (steps, end) in [(10, 3.0), (7, 3.5), (13, 3.2)] {
+            let fields = ["time", "centre", "d", "force", "work"];
+            let mut contents = Header::new(fields.iter().map(|x| x.to_string()).collect())
+                .set("energy_unit", EnergyUnit::KjMol.label())
+                .write();
+            for i in 0..=steps {
+                let centre = 1.0 + (end - 1.0) * i as f64 / steps as f64;
+                contents.push_str(&format!(
+                    "{} {} {} -4.0 {}\n",
+                    i,
+                    centre,
+                    centre - 0.1,
+                    work(centre)
+                ));
+            }
+            let path = dir.join(format!("steered_{}.txt", steps));
+            std::fs::write(&path, contents).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let output = dir.join("jarzynski.txt");
+        let output = output.to_str().unwrap();
+        jarzynski(&paths, 300.0, 5, output).unwrap();
+
+        let rows: Vec<Vec<f64>> = std::fs::read_to_string(output)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|x| x.split_whitespace().map(|x| x.parse().unwrap()).collect())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[4][0], 3.0);
+        for row in rows {
+            assert!((row[1] - work(row[0])).abs() < 1e-5, "{:?}", row);
+            assert!((row[2] - work(row[0])).abs() < 1e-5, "{:?}", row);
+            assert!(row[3].abs() < 1e-5, "{:?}", row);
+        }
+    }
+}
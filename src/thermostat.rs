@@ -0,0 +1,56 @@
+use clap::ArgEnum;
+use rand_distr::{ChiSquared, Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+///Velocity rescaling thermostats. `tau` is the coupling time in fs.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Thermostat {
+    ///weak coupling, Berendsen et al. J. Chem. Phys. 81, 3684 (1984)
+    Berendsen { tau: f64 },
+    ///stochastic velocity rescaling, Bussi et al. J. Chem. Phys. 126, 014101 (2007)
+    Bussi { tau: f64 },
+}
+
+///Thermostats selectable on the command line.
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThermostatKind {
+    Berendsen,
+    Bussi,
+}
+
+impl Thermostat {
+    pub fn new(kind: ThermostatKind, tau: f64) -> Thermostat {
+        match kind {
+            ThermostatKind::Berendsen => Thermostat::Berendsen { tau },
+            ThermostatKind::Bussi => Thermostat::Bussi { tau },
+        }
+    }
+
+    ///factor the velocities are scaled by over a step of `time_step` fs,
+    ///given the kinetic energy and its target in the same unit.
+    pub fn scale(&self, kinetic: f64, target: f64, dof: usize, time_step: f64) -> f64 {
+        if kinetic <= 0.0 || dof == 0 {
+            return 1.0;
+        }
+        match *self {
+            Thermostat::Berendsen { tau } => (1.0 + time_step / tau * (target / kinetic - 1.0))
+                .max(0.0)
+                .sqrt(),
+            Thermostat::Bussi { tau } => {
+                let mut rng = rand::thread_rng();
+                let factor = (-time_step / tau).exp();
+                let r: f64 = StandardNormal.sample(&mut rng);
+                let noise = match dof > 1 {
+                    true => ChiSquared::new((dof - 1) as f64).unwrap().sample(&mut rng),
+                    false => 0.0,
+                };
+                let dof = dof as f64;
+                let new = kinetic
+                    + (1.0 - factor) * (target * (noise + r * r) / dof - kinetic)
+                    + 2.0 * r * (kinetic * target / dof * (1.0 - factor) * factor).sqrt();
+                (new.max(0.0) / kinetic).sqrt()
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::Result;
+
+use crate::cli::Command;
+use crate::metadynamics;
+use crate::steering;
+use crate::trajectory;
+use crate::umbrella;
+
+///Run one of the analysis tools given on the command line.
+pub fn run(command: &Command) -> Result<()> {
+    match command {
+        Command::Convert { input, top, output } => trajectory::convert(input, top, output),
+        Command::Fes {
+            input,
+            bins,
+            output,
+        } => metadynamics::fes(input, *bins, output),
+        Command::Wham {
+            inputs,
+            bins,
+            temperature,
+            skip,
+            bootstrap,
+            output,
+        } => umbrella::wham(inputs, *bins, *temperature, *skip, *bootstrap, output),
+        Command::Jarzynski {
+            inputs,
+            temperature,
+            points,
+            output,
+        } => steering::jarzynski(inputs, *temperature, *points, output),
+    }
+}
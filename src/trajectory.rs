@@ -0,0 +1,229 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::report::frame_header;
+use crate::units::EnergyUnit;
+use crate::vectored::Vectored;
+use crate::{dcd, molden, pdb, xtc};
+
+///Topology written alongside binary trajectories.
+pub const TOPOLOGY: &str = "topology.pdb";
+
+///A single trajectory frame along with the data describing it.
+pub struct Frame<'a> {
+    pub step: usize,
+    ///position of the frame in the trajectory, counted from 0
+    pub index: usize,
+    pub time: f64,
+    pub atoms: &'a [Atom],
+    pub pot_energy: f64,
+    pub kin_energy: f64,
+    pub tot_energy: f64,
+    pub temperature: f64,
+    ///time step that led to the frame in fs
+    pub time_step: f64,
+    pub unit: EnergyUnit,
+}
+
+///Coordinates of a frame read back from a trajectory file.
+pub struct Snapshot {
+    pub step: usize,
+    pub time: f64,
+    pub coords: Vec<[f64; 3]>,
+}
+
+///Supported trajectory file formats.
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TrajFormat {
+    ///plain XYZ, coordinates only
+    #[default]
+    Xyz,
+    ///extended XYZ with energies, velocities and forces
+    Extxyz,
+    ///CHARMM/NAMD binary DCD
+    Dcd,
+    ///GROMACS compressed XTC
+    Xtc,
+    ///multi-model PDB with CONECT records
+    Pdb,
+    ///Molden geometry animation with an energy per frame
+    Molden,
+}
+
+impl TrajFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            TrajFormat::Xyz | TrajFormat::Extxyz => "trajectory.xyz",
+            TrajFormat::Dcd => "trajectory.dcd",
+            TrajFormat::Xtc => "trajectory.xtc",
+            TrajFormat::Pdb => "trajectory.pdb",
+            TrajFormat::Molden => "trajectory.molden",
+        }
+    }
+
+    ///create the trajectory file. Binary formats carry no element
+    ///information, so the topology is written once alongside them.
+    pub fn init(&self, atoms: &[Atom], time_step: f64, interval: usize) {
+        match self {
+            TrajFormat::Dcd => {
+                dcd::write_header(self.file_name(), atoms.len(), time_step, interval)
+            }
+            TrajFormat::Molden => molden::init(self.file_name()),
+            _ => {
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .open(self.file_name())
+                    .expect("failed to init trajectory file");
+            }
+        }
+        if let TrajFormat::Dcd | TrajFormat::Xtc = self {
+            pdb::write_topology(TOPOLOGY, atoms);
+        }
+    }
+
+    ///prepare the file of a restarted run for more frames.
+    pub fn reopen(&self) {
+        if let TrajFormat::Molden = self {
+            molden::reopen(self.file_name());
+        }
+    }
+
+    ///complete the file once the run is over.
+    pub fn finish(&self) {
+        if let TrajFormat::Molden = self {
+            molden::finish(self.file_name());
+        }
+    }
+
+    pub fn write(&self, frame: &Frame) {
+        let to_write = match self {
+            TrajFormat::Xyz => Self::xyz(frame),
+            TrajFormat::Extxyz => Self::extxyz(frame),
+            TrajFormat::Dcd => return dcd::write_frame(self.file_name(), frame.atoms),
+            TrajFormat::Xtc => {
+                return xtc::write_frame(self.file_name(), frame.step, frame.time, frame.atoms)
+            }
+            TrajFormat::Pdb => return pdb::write_model(self.file_name(), frame),
+            TrajFormat::Molden => return molden::write_frame(self.file_name(), frame),
+        };
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(self.file_name())
+            .expect("failed to report trajectory");
+        file.write_all(to_write.as_bytes())
+            .expect("failed to write trajectory frame");
+    }
+
+    fn xyz(frame: &Frame) -> String {
+        let lines = frame
+            .atoms
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} {:.5} {:.5} {:.5}",
+                    x.symbol,
+                    x.pos.as_vec().x,
+                    x.pos.as_vec().y,
+                    x.pos.as_vec().z
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "{}\n{}\n{}\n",
+            frame.atoms.len(),
+            frame_header(frame.step, frame.time),
+            lines
+        )
+    }
+
+    //comment line follows the key=value convention read by ASE and OVITO.
+    //`energy` is picked up by ASE as the potential energy of the frame.
+    //Velocities are in A/fs, energies and forces in the selected unit.
+    fn extxyz(frame: &Frame) -> String {
+        let unit = frame.unit;
+        let comment = format!(
+            "Properties=species:S:1:pos:R:3:velocities:R:3:forces:R:3 \
+             step={} time={:.4} time_step={:.4} energy={:.8} potential_energy={:.8} \
+             kinetic_energy={:.8} total_energy={:.8} temperature={:.4} \
+             energy_unit={} force_unit={} pbc=\"F F F\"",
+            frame.step,
+            frame.time,
+            frame.time_step,
+            unit.convert(frame.pot_energy),
+            unit.convert(frame.pot_energy),
+            unit.convert(frame.kin_energy),
+            unit.convert(frame.tot_energy),
+            frame.temperature,
+            unit.label(),
+            unit.force_label(),
+        );
+        let lines = frame
+            .atoms
+            .iter()
+            .map(|x| {
+                let pos = x.pos.as_vec();
+                let vel = x.vel.as_vec();
+                let force = x.force.as_vec() * unit.convert_md(1.0);
+                format!(
+                    "{:<2} {:>14.8} {:>14.8} {:>14.8} {:>16.8e} {:>16.8e} {:>16.8e} {:>16.8e} {:>16.8e} {:>16.8e}",
+                    x.symbol, pos.x, pos.y, pos.z, vel.x, vel.y, vel.z, force.x, force.y, force.z
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!("{}\n{}\n{}\n", frame.atoms.len(), comment, lines)
+    }
+}
+
+///Read the frames of a binary trajectory, the format is taken from the extension.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Snapshot>> {
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match extension.as_str() {
+        "dcd" => dcd::read(path),
+        "xtc" => xtc::read(path),
+        _ => bail!("unsupported trajectory format: {}", path.as_ref().display()),
+    }
+}
+
+///Convert a binary trajectory into plain XYZ using the element symbols of a topology.
+pub fn convert(input: &str, topology: &str, output: &str) -> Result<()> {
+    let symbols = pdb::read_symbols(topology)?;
+    let frames = read(input)?;
+    let mut to_write = String::new();
+    for frame in &frames {
+        if frame.coords.len() != symbols.len() {
+            bail!(
+                "trajectory has {} atoms but topology has {}",
+                frame.coords.len(),
+                symbols.len()
+            );
+        }
+        to_write.push_str(&format!(
+            "{}\n{}\n",
+            symbols.len(),
+            frame_header(frame.step, frame.time)
+        ));
+        for (symbol, coord) in symbols.iter().zip(&frame.coords) {
+            to_write.push_str(&format!(
+                "{} {:.5} {:.5} {:.5}\n",
+                symbol, coord[0], coord[1], coord[2]
+            ));
+        }
+    }
+    std::fs::write(output, to_write)?;
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use clap::ArgEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::cli::Args;
+use crate::colvar::{Colvar, Harmonic};
+use crate::plumed::{self, Header};
+use crate::report;
+use crate::selection::Selector;
+use crate::simulation::Simulation;
+use crate::units::{self, EnergyUnit};
+use crate::vectored::Force;
+
+//convergence of the WHAM free energies of the windows, in kT
+const WHAM_TOLERANCE: f64 = 1e-8;
+const WHAM_MAX_ITERATIONS: usize = 100_000;
+
+///Harmonic bias k/2 (s - s0)^2 holding a collective variable near the
+///centre of an umbrella window.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Umbrella {
+    #[serde(flatten)]
+    bias: Harmonic,
+}
+
+impl Umbrella {
+    ///add the bias forces in amu A/fs^2 to `forces`, returning the bias
+    ///energy in kJ/mol.
+    pub fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        self.bias.apply(atoms, forces)
+    }
+
+    ///header of umbrella.txt, the centre and force constant in the units
+    ///of colvar.txt and the energy unit.
+    pub fn header(&self, unit: EnergyUnit) -> String {
+        let Harmonic {
+            colvar,
+            centre,
+            force_constant,
+        } = &self.bias;
+        let scale = colvar.display().0;
+        let mut header = Header::new(vec!["time".to_string(), colvar.name.clone()])
+            .set("centre", format!("{:.6}", centre * scale))
+            .set(
+                "force_constant",
+                format!("{:.8}", unit.convert(*force_constant) / (scale * scale)),
+            )
+            .set("energy_unit", unit.label());
+        if colvar.periodic() {
+            header = header.periodic(&colvar.name);
+        }
+        header.write()
+    }
+
+    ///line of umbrella.txt with the current value of the variable.
+    pub fn line(&self, time: f64, atoms: &[Atom]) -> String {
+        let colvar = &self.bias.colvar;
+        let value = colvar.evaluate(atoms).0 * colvar.display().0;
+        format!("{:.2} {:.6}\n", time, value)
+    }
+
+    ///the current value and the centre as logged.
+    pub fn describe(&self, atoms: &[Atom]) -> String {
+        let colvar = &self.bias.colvar;
+        let (scale, unit) = colvar.display();
+        format!(
+            "{} is {:.3} {unit}, window centre {:.3} {unit}",
+            colvar.name,
+            colvar.evaluate(atoms).0 * scale,
+            self.bias.centre * scale
+        )
+    }
+}
+
+///Windows of umbrella sampling evenly spaced along a collective variable.
+struct Windows {
+    colvar: Colvar,
+    centres: Vec<f64>,
+    force_constant: f64,
+}
+
+impl Windows {
+    //parse `NAME:START:END:N` with the ends in the units of colvar.txt.
+    fn new(args: &Args, atoms: &[Atom]) -> Result<Windows> {
+        let spec = args.umbrella.as_deref().context("no --umbrella given")?;
+        let path = args
+            .colvars
+            .as_deref()
+            .context("--umbrella needs --colvars")?;
+        let parts: Vec<&str> = spec.split(':').collect();
+        let (name, start, end, count) = match parts.as_slice() {
+            [name, start, end, count] => (*name, *start, *end, *count),
+            _ => bail!("invalid --umbrella \"{}\", expected NAME:START:END:N", spec),
+        };
+        let number = |x: &str| {
+            x.trim()
+                .parse::<f64>()
+                .with_context(|| format!("invalid window centre \"{}\"", x))
+        };
+        let (start, end) = (number(start)?, number(end)?);
+        let count = count
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|x| *x >= 2)
+            .with_context(|| format!("invalid number of windows \"{}\", at least 2", count))?;
+        let selector = Selector::new(atoms, &args.group)?;
+        let colvar = Colvar::load(path, atoms, &selector)?
+            .into_iter()
+            .find(|x| x.name == name)
+            .with_context(|| format!("unknown collective variable \"{}\"", name))?;
+        let force_constant = args
+            .umbrella_k
+            .filter(|x| *x > 0.0)
+            .context("--umbrella needs a positive --umbrella-k")?;
+        let scale = colvar.display().0;
+        let centres = (0..count)
+            .map(|i| (start + (end - start) * i as f64 / (count - 1) as f64) / scale)
+            .collect();
+        Ok(Windows {
+            colvar,
+            centres,
+            force_constant: force_constant / args.energy_unit.convert(1.0),
+        })
+    }
+
+    fn umbrella(&self, index: usize) -> Umbrella {
+        Umbrella {
+            bias: Harmonic {
+                colvar: self.colvar.clone(),
+                centre: self.centres[index],
+                force_constant: self.force_constant,
+            },
+        }
+    }
+}
+
+fn dir_name(index: usize) -> String {
+    format!("window_{:02}", index + 1)
+}
+
+///Run the windows of umbrella sampling, each in its own directory and
+///starting from the final state of the window before. With
+///`--umbrella-window` only that window runs, from the input structure.
+///On restart the last window with a checkpoint is resumed and the rest follow.
+pub fn run(args: &Args) -> Result<()> {
+    let mut simulation = Simulation::new(args)?;
+    let windows = Windows::new(args, simulation.atoms())?;
+    let root = std::env::current_dir()?;
+    let indices: Vec<usize> = match args.umbrella_window {
+        Some(n) if n == 0 || n > windows.centres.len() => {
+            bail!("window {} is outside 1-{}", n, windows.centres.len())
+        }
+        Some(n) => vec![n - 1],
+        None => (0..windows.centres.len()).collect(),
+    };
+
+    let mut first = 0;
+    if args.restart {
+        let resume = indices
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, index)| has_checkpoint(&root.join(dir_name(**index))));
+        let (position, index) = match resume {
+            Some(x) => x,
+            None => bail!("no window checkpoint found to restart from"),
+        };
+        std::env::set_current_dir(root.join(dir_name(*index)))?;
+        simulation = Simulation::from_save(args)?.run()?;
+        first = position + 1;
+    }
+
+    for &index in indices.iter().skip(first) {
+        let dir = root.join(dir_name(index));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        std::env::set_current_dir(&dir)?;
+        simulation = simulation
+            .begin_window(windows.umbrella(index), index, &root)
+            .run()?;
+    }
+    std::env::set_current_dir(root)?;
+    Ok(())
+}
+
+fn has_checkpoint(dir: &Path) -> bool {
+    std::fs::metadata(dir.join("save.json"))
+        .map(|x| x.len() > 0)
+        .unwrap_or(false)
+}
+
+//samples of a window as read from its umbrella.txt.
+struct Samples {
+    values: Vec<f64>,
+    centre: f64,
+    force_constant: f64,
+}
+
+///Combine the windows of umbrella sampling into the potential of mean
+///force along the variable with WHAM, Kumar et al. J. Comput. Chem. 13,
+///1011 (1992), on `bins` bins at `temperature` in K. The first `skip`
+///samples of every window are left out as equilibration, and errors are
+///the spread of `bootstrap` resamplings of the windows.
+pub fn wham(
+    paths: &[String],
+    bins: usize,
+    temperature: f64,
+    skip: usize,
+    bootstrap: usize,
+    output: &str,
+) -> Result<()> {
+    if bins < 2 || temperature <= 0.0 {
+        bail!("WHAM needs at least two bins and a positive temperature");
+    }
+    let mut name = String::new();
+    let mut unit = String::new();
+    let mut period = None;
+    let mut windows = vec![];
+    for path in paths {
+        let (window, variable) = read_samples(path, skip)?;
+        if !name.is_empty() && variable.name != name {
+            bail!("{} holds {}, not {}", path, variable.name, name);
+        }
+        if !unit.is_empty() && variable.unit != unit {
+            bail!("{} is in {}, not {}", path, variable.unit, unit);
+        }
+        (name, unit, period) = (variable.name, variable.unit, variable.period);
+        windows.push(window);
+    }
+    if windows.len() < 2 {
+        bail!("WHAM needs at least two windows");
+    }
+    let energy_unit = EnergyUnit::value_variants()
+        .iter()
+        .find(|x| x.label() == unit)
+        .with_context(|| format!("unknown energy unit \"{}\"", unit))?;
+    let kt = energy_unit.convert(units::kt(temperature));
+
+    let (low, high) = match period {
+        Some(x) => x,
+        None => windows
+            .iter()
+            .flat_map(|x| x.values.iter())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), x| {
+                (low.min(*x), high.max(*x))
+            }),
+    };
+    if high <= low {
+        bail!("the windows sample a single value");
+    }
+    let width = (high - low) / bins as f64;
+    let centres: Vec<f64> = (0..bins).map(|i| low + (i as f64 + 0.5) * width).collect();
+    let difference = |x: f64, y: f64| {
+        let delta = x - y;
+        match period {
+            Some((min, max)) => delta - (max - min) * (delta / (max - min)).round(),
+            None => delta,
+        }
+    };
+    //Boltzmann factor of the bias of every window at every bin
+    let factors: Vec<Vec<f64>> = windows
+        .iter()
+        .map(|w| {
+            centres
+                .iter()
+                .map(|x| {
+                    let delta = difference(*x, w.centre);
+                    (-0.5 * w.force_constant * delta * delta / kt).exp()
+                })
+                .collect()
+        })
+        .collect();
+    let bin = |x: f64| (((x - low) / width) as usize).min(bins - 1);
+    let histogram = |values: &mut dyn Iterator<Item = f64>| {
+        let mut counts = vec![0.0; bins];
+        values.for_each(|x| counts[bin(x)] += 1.0);
+        counts
+    };
+
+    let histograms: Vec<Vec<f64>> = windows
+        .iter()
+        .map(|w| histogram(&mut w.values.iter().copied()))
+        .collect();
+    let pmf = solve(&histograms, &factors, kt)?;
+
+    let mut rng = rand::thread_rng();
+    let mut resampled: Vec<Vec<Option<f64>>> = vec![];
+    for _ in 0..bootstrap {
+        let histograms: Vec<Vec<f64>> = windows
+            .iter()
+            .map(|w| {
+                let n = w.values.len();
+                histogram(&mut (0..n).map(|_| w.values[rng.gen_range(0..n)]))
+            })
+            .collect();
+        resampled.push(solve(&histograms, &factors, kt)?);
+    }
+    //spread of the resampled profiles, each shifted to a minimum of 0
+    let errors: Vec<Option<f64>> = (0..bins)
+        .map(|b| {
+            let values: Vec<f64> = resampled.iter().filter_map(|x| x[b]).collect();
+            if values.len() < 2 || pmf[b].is_none() {
+                return None;
+            }
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance =
+                values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+            Some(variance.sqrt())
+        })
+        .collect();
+
+    let mut columns = vec![
+        name,
+        format!("Free energy {}", unit),
+        format!("Error {}", unit),
+    ];
+    if bootstrap == 0 {
+        columns.pop();
+    }
+    let mut to_write = report::columns(&columns);
+    for ((x, value), error) in centres.iter().zip(&pmf).zip(&errors) {
+        let value = match value {
+            Some(value) => value,
+            None => continue,
+        };
+        let line = match (bootstrap, error) {
+            (0, _) => format!("{:<30.6} {:.6}", x, value),
+            (_, Some(error)) => format!("{:<30.6} {:<30.6} {:.6}", x, value, error),
+            (_, None) => format!("{:<30.6} {:<30.6} nan", x, value),
+        };
+        to_write.push_str(&line);
+        to_write.push('\n');
+    }
+    std::fs::write(output, to_write).with_context(|| format!("failed to write {}", output))?;
+    Ok(())
+}
+
+//potential of mean force on the bins from the histograms of the windows,
+//None where nothing was sampled, shifted to a minimum of 0.
+fn solve(histograms: &[Vec<f64>], factors: &[Vec<f64>], kt: f64) -> Result<Vec<Option<f64>>> {
+    let bins = histograms[0].len();
+    let totals: Vec<f64> = histograms.iter().map(|x| x.iter().sum()).collect();
+    let counts: Vec<f64> = (0..bins)
+        .map(|b| histograms.iter().map(|x| x[b]).sum())
+        .collect();
+    //exp(f_i / kT) of every window
+    let mut shifts = vec![1.0; histograms.len()];
+    let mut probability = vec![0.0; bins];
+    let mut converged = false;
+    for _ in 0..WHAM_MAX_ITERATIONS {
+        for (b, p) in probability.iter_mut().enumerate() {
+            let denominator: f64 = totals
+                .iter()
+                .zip(&shifts)
+                .zip(factors)
+                .map(|((n, f), c)| n * f * c[b])
+                .sum();
+            *p = if counts[b] > 0.0 {
+                counts[b] / denominator
+            } else {
+                0.0
+            };
+        }
+        let next: Vec<f64> = factors
+            .iter()
+            .map(|c| 1.0 / c.iter().zip(&probability).map(|(c, p)| c * p).sum::<f64>())
+            .collect();
+        let change = next
+            .iter()
+            .zip(&shifts)
+            .map(|(x, y)| (x / y).ln().abs())
+            .fold(0.0, f64::max);
+        shifts = next;
+        if change < WHAM_TOLERANCE {
+            converged = true;
+            break;
+        }
+    }
+    if !converged || shifts.iter().any(|x| !x.is_finite()) {
+        bail!("WHAM did not converge, do the windows overlap?");
+    }
+    let pmf: Vec<Option<f64>> = probability
+        .iter()
+        .map(|p| (*p > 0.0).then(|| -kt * p.ln()))
+        .collect();
+    let minimum = pmf.iter().flatten().cloned().fold(f64::INFINITY, f64::min);
+    Ok(pmf.into_iter().map(|x| x.map(|x| x - minimum)).collect())
+}
+
+//the variable and units shared by the windows.
+struct Variable {
+    name: String,
+    unit: String,
+    period: Option<(f64, f64)>,
+}
+
+fn read_samples(path: &str, skip: usize) -> Result<(Samples, Variable)> {
+    let (header, rows) = plumed::read(path)?;
+    let name = match header.fields.as_slice() {
+        [time, name] if time == "time" => name.clone(),
+        _ => bail!("{} is not an umbrella.txt", path),
+    };
+    let centre = header
+        .number("centre", path)?
+        .with_context(|| format!("{} has no centre", path))?;
+    let force_constant = header
+        .number("force_constant", path)?
+        .with_context(|| format!("{} has no force constant", path))?;
+    if rows.len() <= skip {
+        bail!("{} holds no samples after skipping {}", path, skip);
+    }
+    let samples = Samples {
+        values: rows[skip..].iter().map(|x| x[1]).collect(),
+        centre,
+        force_constant,
+    };
+    let variable = Variable {
+        period: header.period(&name, path)?,
+        unit: header.get("energy_unit").unwrap_or_default().to_string(),
+        name,
+    };
+    Ok((samples, variable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //two overlapping windows on a flat potential, sampled with the exact
+    //biased histograms at the bin centres, give a flat profile.
+    #[test]
+    fn wham_flat_potential() {
+        let dir = std::env::temp_dir().join(format!("ezaimd_wham_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let temperature = 300.0;
+        let kt = units::kt(temperature);
+        let force_constant = kt / 30.0_f64.powi(2);
+        let centres: Vec<f64> = (0..36).map(|i| -175.0 + 10.0 * i as f64).collect();
+        let counts = |centre: f64| -> Vec<usize> {
+            centres
+                .iter()
+                .map(|x| {
+                    let bias = 0.5 * force_constant * (x - centre).powi(2);
+                    (1e4 * (-bias / kt).exp()).round() as usize
+                })
+                .collect()
+        };
+        let mut paths = vec![];
+        for centre in [-10.0, 10.0] {
+            let header = Header::new(vec!["time".to_string(), "phi".to_string()])
+                .set("centre", centre)
+                .set("force_constant", force_constant)
+                .set("energy_unit", EnergyUnit::KjMol.label())
+                .periodic("phi");
+            let mut contents = header.write();
+            for (x, count) in centres.iter().zip(counts(centre)) {
+                for _ in 0..count {
+                    contents.push_str(&format!("0.00 {}\n", x));
+                }
+            }
+            let path = dir.join(format!("umbrella_{}.txt", centre));
+            std::fs::write(&path, contents).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let output = dir.join("pmf.txt");
+        let output = output.to_str().unwrap();
+        wham(&paths, 36, temperature, 0, 0, output).unwrap();
+
+        let pmf: Vec<(f64, f64)> = std::fs::read_to_string(output)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|x| {
+                let values: Vec<f64> = x.split_whitespace().map(|x| x.parse().unwrap()).collect();
+                (values[0], values[1])
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        //bins sampled at least 100 times by both windows
+        let (low, high) = (counts(-10.0), counts(10.0));
+        let sampled: Vec<f64> = pmf
+            .iter()
+            .filter(|(x, _)| {
+                let b = centres.iter().position(|c| (c - x).abs() < 1e-6).unwrap();
+                low[b] >= 100 && high[b] >= 100
+            })
+            .map(|x| x.1)
+            .collect();
+        assert!(sampled.len() > 15);
+        let spread = sampled.iter().fold(f64::NEG_INFINITY, |a, x| a.max(*x))
+            - sampled.iter().fold(f64::INFINITY, |a, x| a.min(*x));
+        assert!(spread < 0.03, "{:?}", sampled);
+    }
+}
@@ -0,0 +1,123 @@
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+//Physical constants, CODATA 2018 recommended values.
+
+///Avogadro constant, mol^-1
+pub const AVOGADRO: f64 = 6.022_140_76e23;
+///Boltzmann constant, J/K
+pub const BOLTZMANN: f64 = 1.380_649e-23;
+///molar gas constant, J/(mol K)
+pub const GAS_CONSTANT: f64 = BOLTZMANN * AVOGADRO;
+///Hartree energy, J
+pub const HARTREE: f64 = 4.359_744_722_207_1e-18;
+///electron volt, J
+pub const ELECTRON_VOLT: f64 = 1.602_176_634e-19;
+///thermochemical calorie, J
+pub const CALORIE: f64 = 4.184;
+///atomic mass constant, kg
+pub const ATOMIC_MASS: f64 = 1.660_539_066_60e-27;
+///Bohr radius, Angstrom
+pub const BOHR: f64 = 0.529_177_210_903;
+
+//Internally the simulation works in Angstrom, fs and amu. Energies
+//are kept in kJ/mol and converted to the selected unit on output.
+
+///Hartree in kJ/mol
+pub const HARTREE_KJ_MOL: f64 = HARTREE * AVOGADRO / 1000.0;
+///electron volt in kJ/mol
+pub const EV_KJ_MOL: f64 = ELECTRON_VOLT * AVOGADRO / 1000.0;
+///amu A^2/fs^2, the energy of the internal unit system, in kJ/mol
+pub const MD_ENERGY_KJ_MOL: f64 = ATOMIC_MASS * AVOGADRO * 1e10 / 1000.0;
+///Hartree/Bohr in amu A/fs^2
+pub const HARTREE_BOHR_MD_FORCE: f64 = HARTREE_KJ_MOL / BOHR / MD_ENERGY_KJ_MOL;
+
+///thermal energy k_B T in kJ/mol.
+pub fn kt(temperature: f64) -> f64 {
+    GAS_CONSTANT * temperature / 1000.0
+}
+
+///Energy units available for output.
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EnergyUnit {
+    KcalMol,
+    #[default]
+    KjMol,
+    Ev,
+    Hartree,
+}
+
+impl EnergyUnit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EnergyUnit::KcalMol => "kcal/mol",
+            EnergyUnit::KjMol => "kJ/mol",
+            EnergyUnit::Ev => "eV",
+            EnergyUnit::Hartree => "Hartree",
+        }
+    }
+
+    ///label of forces written in this energy unit per Angstrom.
+    pub fn force_label(&self) -> String {
+        format!("{}/A", self.label())
+    }
+
+    ///convert an energy in kJ/mol into this unit.
+    pub fn convert(&self, value: f64) -> f64 {
+        match self {
+            EnergyUnit::KcalMol => value / CALORIE,
+            EnergyUnit::KjMol => value,
+            EnergyUnit::Ev => value / EV_KJ_MOL,
+            EnergyUnit::Hartree => value / HARTREE_KJ_MOL,
+        }
+    }
+
+    ///convert an energy in amu A^2/fs^2 into this unit.
+    pub fn convert_md(&self, value: f64) -> f64 {
+        self.convert(value * MD_ENERGY_KJ_MOL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(value: f64, expected: f64) -> bool {
+        (value - expected).abs() <= 1e-9 * expected.abs()
+    }
+
+    //the derived constants against the values CODATA 2018 publishes for
+    //them, so that a typo in a primitive shows up
+    #[test]
+    fn derived_constants() {
+        //molar gas constant
+        assert!(close(GAS_CONSTANT, 8.314_462_618));
+        //Hartree energy in kJ/mol and eV
+        assert!(close(HARTREE_KJ_MOL, 2_625.499_639_479_9));
+        assert!(close(HARTREE / ELECTRON_VOLT, 27.211_386_245_988));
+        //Faraday constant in kC/mol
+        assert!(close(EV_KJ_MOL, 96.485_332_12));
+        //molar mass constant 0.999 999 999 65e-3 kg/mol times (A/fs)^2
+        assert!(close(MD_ENERGY_KJ_MOL, 0.999_999_999_65e4));
+        //Hartree/Bohr in amu A/fs^2
+        assert!(close(HARTREE_BOHR_MD_FORCE, 0.496_147_526_065_7));
+        assert!(close(kt(300.0), 2.494_338_785_4));
+    }
+
+    #[test]
+    fn energy_units() {
+        assert!(close(EnergyUnit::KcalMol.convert(4.184), 1.0));
+        assert!(close(EnergyUnit::KjMol.convert(1.0), 1.0));
+        assert!(close(EnergyUnit::Ev.convert(96.485_332_12), 1.0));
+        assert!(close(EnergyUnit::Hartree.convert(2_625.499_639_479_9), 1.0));
+        assert!(close(
+            EnergyUnit::Ev.convert(HARTREE_KJ_MOL),
+            27.211_386_245_988
+        ));
+        //1 amu A^2/fs^2 is 1e7 J/mol less the defect of the molar mass constant
+        assert!(close(
+            EnergyUnit::KcalMol.convert_md(1.0),
+            0.999_999_999_65e4 / 4.184
+        ));
+    }
+}
@@ -1,7 +1,7 @@
 use num_traits::Float;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
-use std::ops::{Add, Mul, Sub};
+use std::ops::{Add, Div, Mul, Sub};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Vector3D<T: Float> {
@@ -14,6 +14,18 @@ impl<T: Float> Vector3D<T> {
     pub fn new(x: T, y: T, z: T) -> Vector3D<T> {
         Vector3D { x, y, z }
     }
+
+    pub fn dot(&self, rhs: Vector3D<T>) -> T {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn cross(&self, rhs: Vector3D<T>) -> Vector3D<T> {
+        Vector3D {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
+        }
+    }
 }
 
 impl<T: Float> Mul<T> for Vector3D<T> {
@@ -27,6 +39,17 @@ impl<T: Float> Mul<T> for Vector3D<T> {
     }
 }
 
+impl<T: Float> Div<T> for Vector3D<T> {
+    type Output = Self;
+    fn div(self, rhs: T) -> Self::Output {
+        Self {
+            x: self.x / rhs,
+            y: self.y / rhs,
+            z: self.z / rhs,
+        }
+    }
+}
+
 impl Mul<Vector3D<f64>> for f64 {
     type Output = Vector3D<f64>;
     fn mul(self, rhs: Vector3D<f64>) -> Self::Output {
@@ -16,17 +16,11 @@ pub trait Vectored<T: Float> {
     fn norm(&self) -> T {
         self.sqr_norm().sqrt()
     }
-
-    fn normalize(mut self) -> Self
-    where
-        Self: Sized,
-    {
-        let result = self.as_vec() * self.norm().recip();
-        self.set_vec(result);
-        self
-    }
 }
 
+///Generate a vector quantity. Only scaling by a plain number is provided,
+///arithmetic between quantities is opted into with `make_linear!` or
+///the relations in `quantity.rs`.
 #[macro_export]
 macro_rules! make_vectored {
     ($expression:ident) => {
@@ -47,82 +41,91 @@ macro_rules! make_vectored {
             }
         }
 
-        impl<T> $expression<T>
+        impl<T> std::ops::Mul<T> for $expression<T>
         where
             T: num_traits::Float,
         {
-            pub fn as_vectype<U>(self) -> U
-            where
-                U: crate::vectored::Vectored<T>,
-            {
-                let x = self.as_vec().x;
-                let y = self.as_vec().y;
-                let z = self.as_vec().z;
-
-                U::new(x, y, z)
+            type Output = $expression<T>;
+            fn mul(mut self, rhs: T) -> Self {
+                let result = self.as_vec() * rhs;
+                self.set_vec(result);
+                self
+            }
+        }
+
+        impl std::ops::Mul<$expression<f64>> for f64 {
+            type Output = $expression<f64>;
+            fn mul(self, mut rhs: $expression<f64>) -> Self::Output {
+                let result = self * rhs.as_vec();
+                rhs.set_vec(result);
+                rhs
+            }
+        }
+
+        impl std::ops::Mul<$expression<f32>> for f32 {
+            type Output = $expression<f32>;
+            fn mul(self, mut rhs: $expression<f32>) -> Self::Output {
+                let result = self * rhs.as_vec();
+                rhs.set_vec(result);
+                rhs
             }
         }
+    };
+}
 
-        impl<T, U> std::ops::Add<U> for $expression<T>
+///Quantities forming a vector space: they may be added to and
+///subtracted from quantities of the same kind, and summed.
+#[macro_export]
+macro_rules! make_linear {
+    ($expression:ident) => {
+        impl<T> std::ops::Add for $expression<T>
         where
             T: num_traits::Float,
-            U: Vectored<T>,
         {
             type Output = $expression<T>;
-            fn add(mut self, rhs: U) -> Self::Output {
+            fn add(mut self, rhs: Self) -> Self::Output {
                 let result = self.as_vec() + rhs.as_vec();
                 self.set_vec(result);
                 self
             }
         }
 
-        impl<T, U> std::ops::Sub<U> for $expression<T>
+        impl<T> std::ops::Sub for $expression<T>
         where
             T: num_traits::Float,
-            U: crate::vectored::Vectored<T>,
         {
             type Output = $expression<T>;
-            fn sub(mut self, rhs: U) -> Self::Output {
+            fn sub(mut self, rhs: Self) -> Self::Output {
                 let result = self.as_vec() - rhs.as_vec();
                 self.set_vec(result);
                 self
             }
         }
 
-        impl<T> std::ops::Mul<T> for $expression<T>
+        impl<T> std::iter::Sum for $expression<T>
         where
             T: num_traits::Float,
         {
-            type Output = $expression<T>;
-            fn mul(mut self, rhs: T) -> Self {
-                let result = self.as_vec() * rhs;
-                self.set_vec(result);
-                self
-            }
-        }
-
-        impl std::ops::Mul<$expression<f64>> for f64 {
-            type Output = $expression<f64>;
-            fn mul(self, mut rhs: $expression<f64>) -> Self::Output {
-                let result = self * rhs.as_vec();
-                rhs.set_vec(result);
-                rhs
-            }
-        }
-
-        impl std::ops::Mul<$expression<f32>> for f32 {
-            type Output = $expression<f32>;
-            fn mul(self, mut rhs: $expression<f32>) -> Self::Output {
-                let result = self * rhs.as_vec();
-                rhs.set_vec(result);
-                rhs
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold(Self::new(T::zero(), T::zero(), T::zero()), |a, b| a + b)
             }
         }
     };
 }
 
+//Units: Position and Displacement in A, Velocity in A/fs, Acceleration
+//in A/fs^2, Force in amu A/fs^2, Momentum in amu A/fs. AtomicForce is
+//Hartree/Bohr, as read from Gaussian16, and must be converted to a Force.
 make_vectored!(Acceleration);
+make_vectored!(AtomicForce);
+make_vectored!(Displacement);
 make_vectored!(Force);
 make_vectored!(Momentum);
 make_vectored!(Position);
 make_vectored!(Velocity);
+
+make_linear!(Acceleration);
+make_linear!(Displacement);
+make_linear!(Force);
+make_linear!(Momentum);
+make_linear!(Velocity);
@@ -0,0 +1,189 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::units::{self, EnergyUnit};
+use crate::vector::Vector3D;
+use crate::vectored::{Force, Vectored};
+
+///Flat-bottom harmonic wall of an ellipsoid, a sphere when all radii are
+///equal. An atom at d from the centre, outside the surface by
+///e = |d| (1 - 1/s) along its ray with s^2 = sum (d_k/a_k)^2, adds k/2 e^2.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Wall {
+    ///semi-axes along x, y and z in A
+    pub radii: [f64; 3],
+    ///force constant in kJ/mol/A^2
+    pub force_constant: f64,
+    ///fixed centre in A, the centre of mass of all atoms when not given
+    pub centre: Option<[f64; 3]>,
+}
+
+impl Wall {
+    ///parse `RADII:K` with one radius or three comma separated semi-axes
+    ///in A and K in `unit` per A^2, and an optional centre `X,Y,Z` in A.
+    pub fn new(spec: &str, centre: Option<&str>, unit: EnergyUnit) -> Result<Wall> {
+        let (radii, k) = spec
+            .rsplit_once(':')
+            .with_context(|| format!("invalid --wall \"{}\", expected RADII:K", spec))?;
+        let radii = match numbers(radii, "radius")?.as_slice() {
+            [r] => [*r; 3],
+            [x, y, z] => [*x, *y, *z],
+            _ => bail!(
+                "a wall takes one radius or three semi-axes, not \"{}\"",
+                radii
+            ),
+        };
+        if radii.iter().any(|x| *x <= 0.0) {
+            bail!("the radii of the wall must be positive");
+        }
+        let k = k
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|x| *x > 0.0)
+            .with_context(|| format!("invalid force constant \"{}\"", k))?;
+        let centre = match centre {
+            Some(x) => match numbers(x, "coordinate")?.as_slice() {
+                [x, y, z] => Some([*x, *y, *z]),
+                _ => bail!(
+                    "the centre of the wall needs three coordinates, not \"{}\"",
+                    x
+                ),
+            },
+            None => None,
+        };
+        Ok(Wall {
+            radii,
+            force_constant: k / unit.convert(1.0),
+            centre,
+        })
+    }
+
+    ///add the wall forces in amu A/fs^2 to `forces`, returning the wall
+    ///energy in kJ/mol. With the centre of mass as centre, the forces
+    ///pulling atoms back are balanced over all atoms by mass.
+    pub fn apply(&self, atoms: &[Atom], forces: &mut [Force<f64>]) -> f64 {
+        let centre = self.centre(atoms);
+        let zero = Vector3D::new(0.0, 0.0, 0.0);
+        let mut energy = 0.0;
+        let mut gradients = vec![zero; atoms.len()];
+        for (atom, gradient) in atoms.iter().zip(gradients.iter_mut()) {
+            let (excess, direction) = self.excess(atom.pos.as_vec() - centre);
+            energy += 0.5 * self.force_constant * excess * excess;
+            *gradient = direction * (self.force_constant * excess);
+        }
+        let total = gradients.iter().fold(zero, |a, x| a + *x);
+        let total_mass: f64 = atoms.iter().map(|x| x.mass.as_amu()).sum();
+        for ((atom, gradient), force) in atoms.iter().zip(gradients).zip(forces.iter_mut()) {
+            let gradient = match self.centre {
+                Some(_) => gradient,
+                None => gradient - total * (atom.mass.as_amu() / total_mass),
+            };
+            let f = gradient * (-1.0 / units::MD_ENERGY_KJ_MOL);
+            *force = *force + Force::new(f.x, f.y, f.z);
+        }
+        energy
+    }
+
+    ///for every atom, whether it is outside the flat bottom.
+    pub fn touching(&self, atoms: &[Atom]) -> Vec<bool> {
+        let centre = self.centre(atoms);
+        atoms
+            .iter()
+            .map(|x| self.excess(x.pos.as_vec() - centre).0 > 0.0)
+            .collect()
+    }
+
+    fn centre(&self, atoms: &[Atom]) -> Vector3D<f64> {
+        match self.centre {
+            Some([x, y, z]) => Vector3D::new(x, y, z),
+            None => {
+                let total_mass: f64 = atoms.iter().map(|x| x.mass.as_amu()).sum();
+                atoms.iter().fold(Vector3D::new(0.0, 0.0, 0.0), |a, x| {
+                    a + x.mass.as_amu() * x.pos.as_vec()
+                }) / total_mass
+            }
+        }
+    }
+
+    //distance outside the surface along the ray from the centre and its
+    //gradient with respect to `d`, zero inside.
+    fn excess(&self, d: Vector3D<f64>) -> (f64, Vector3D<f64>) {
+        let zero = Vector3D::new(0.0, 0.0, 0.0);
+        let [a, b, c] = self.radii;
+        let scaled = Vector3D::new(d.x / a, d.y / b, d.z / c);
+        let s = scaled.dot(scaled).sqrt();
+        if s <= 1.0 {
+            return (0.0, zero);
+        }
+        let length = d.dot(d).sqrt();
+        let grad_s = Vector3D::new(d.x / (a * a), d.y / (b * b), d.z / (c * c)) / s;
+        let gradient = d / length * (1.0 - 1.0 / s) + grad_s * (length / (s * s));
+        (length * (1.0 - 1.0 / s), gradient)
+    }
+}
+
+fn numbers(text: &str, name: &str) -> Result<Vec<f64>> {
+    text.split(',')
+        .map(|x| {
+            x.trim()
+                .parse::<f64>()
+                .with_context(|| format!("invalid {} \"{}\"", name, x))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::geometry::check_gradient;
+    use crate::vectored::Position;
+
+    fn wall(centre: Option<[f64; 3]>) -> Wall {
+        Wall {
+            radii: [1.0, 1.5, 2.0],
+            force_constant: 80.0,
+            centre,
+        }
+    }
+
+    #[test]
+    fn excess_gradient() {
+        let wall = wall(None);
+        let f = |p: &[Vector3D<f64>]| {
+            let (value, gradient) = wall.excess(p[0]);
+            (value, vec![gradient])
+        };
+        for [x, y, z] in [[1.5, 0.3, -0.2], [0.1, -2.0, 0.4], [-0.9, 1.2, 2.5]] {
+            let d = Vector3D::new(x, y, z);
+            assert!(wall.excess(d).0 > 0.0);
+            check_gradient(f, &[d], None);
+        }
+        assert_eq!(wall.excess(Vector3D::new(0.5, 0.5, 0.5)).0, 0.0);
+    }
+
+    #[test]
+    fn apply_gradient() {
+        let points = [[1.6, 0.3, -0.2], [0.1, -0.4, 0.2], [-0.9, 1.8, 2.5]]
+            .map(|[x, y, z]| Vector3D::new(x, y, z));
+        for wall in [wall(None), wall(Some([0.2, -0.1, 0.3]))] {
+            let f = |p: &[Vector3D<f64>]| {
+                let mut atoms = test_atoms(&[("C", [0.0; 3]), ("H", [0.0; 3]), ("C", [0.0; 3])]);
+                for (atom, p) in atoms.iter_mut().zip(p) {
+                    atom.pos = Position::new(p.x, p.y, p.z);
+                }
+                let mut forces = vec![Force::new(0.0, 0.0, 0.0); atoms.len()];
+                let energy = wall.apply(&atoms, &mut forces);
+                let gradient = forces
+                    .iter()
+                    .map(|x| x.as_vec() * -units::MD_ENERGY_KJ_MOL)
+                    .collect();
+                (energy, gradient)
+            };
+            assert!(f(&points).0 > 0.0);
+            check_gradient(f, &points, None);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::atom::Atom;
+use crate::cli::Args;
+use crate::units::{self, EnergyUnit};
+use crate::vectored::Vectored;
+
+///What the simulation does once a guard trips.
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TripAction {
+    ///write the diagnostic snapshot and end the run
+    #[default]
+    Stop,
+    ///return to the previous step and retry it with half the time step
+    Rollback,
+}
+
+///Guards checked after every step. Energies are kept in kJ/mol and
+///forces in kJ/mol/A, a guard left as None is not checked.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Watchdog {
+    pub max_drift: Option<f64>,
+    pub max_jump: Option<f64>,
+    pub max_force: Option<f64>,
+    pub min_distance: Option<f64>,
+    pub max_temperature: Option<f64>,
+    pub action: TripAction,
+    pub max_retries: usize,
+}
+
+///State of the system after a step, as seen by the watchdog.
+pub struct Observation<'a> {
+    pub atoms: &'a [Atom],
+    ///conserved energy minus that of step 0, kJ/mol
+    pub drift: f64,
+    ///change of the conserved energy over the step, kJ/mol
+    pub jump: f64,
+    pub temperature: f64,
+}
+
+impl Watchdog {
+    ///thresholds on the command line are given in the output energy unit.
+    pub fn new(args: &Args) -> Watchdog {
+        let unit = args.energy_unit;
+        let to_kj_mol = |x: f64| x / unit.convert(1.0);
+        Watchdog {
+            max_drift: args.max_drift.map(to_kj_mol),
+            max_jump: args.max_jump.map(to_kj_mol),
+            max_force: args.max_force.map(to_kj_mol),
+            min_distance: args.min_distance,
+            max_temperature: args.max_temperature,
+            action: args.on_trip,
+            max_retries: args.max_retries,
+        }
+    }
+
+    ///reason for the first guard that trips, if any.
+    pub fn check(&self, observation: &Observation, unit: EnergyUnit) -> Option<String> {
+        if let Some(limit) = self.max_drift {
+            if observation.drift.abs() > limit {
+                return Some(format!(
+                    "energy drift {:.6} {} exceeds {:.6} {}",
+                    unit.convert(observation.drift),
+                    unit.label(),
+                    unit.convert(limit),
+                    unit.label()
+                ));
+            }
+        }
+        if let Some(limit) = self.max_jump {
+            if observation.jump.abs() > limit {
+                return Some(format!(
+                    "conserved energy changed by {:.6} {} in one step, limit {:.6} {}",
+                    unit.convert(observation.jump),
+                    unit.label(),
+                    unit.convert(limit),
+                    unit.label()
+                ));
+            }
+        }
+        if let Some(limit) = self.max_force {
+            for (i, atom) in observation.atoms.iter().enumerate() {
+                let force = atom.force.norm() * units::MD_ENERGY_KJ_MOL;
+                if force > limit {
+                    return Some(format!(
+                        "force on atom {} ({}) of {:.6} {} exceeds {:.6} {}",
+                        i + 1,
+                        atom.symbol,
+                        unit.convert(force),
+                        unit.force_label(),
+                        unit.convert(limit),
+                        unit.force_label()
+                    ));
+                }
+            }
+        }
+        if let Some(limit) = self.min_distance {
+            let atoms = observation.atoms;
+            for i in 0..atoms.len() {
+                for j in (i + 1)..atoms.len() {
+                    let distance = (atoms[i].pos - atoms[j].pos).norm();
+                    if distance < limit {
+                        return Some(format!(
+                            "atoms {} ({}) and {} ({}) are {:.4} A apart, minimum {:.4} A",
+                            i + 1,
+                            atoms[i].symbol,
+                            j + 1,
+                            atoms[j].symbol,
+                            distance,
+                            limit
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(limit) = self.max_temperature {
+            if observation.temperature > limit {
+                return Some(format!(
+                    "temperature {:.2} K exceeds {:.2} K",
+                    observation.temperature, limit
+                ));
+            }
+        }
+        None
+    }
+}
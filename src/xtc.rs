@@ -0,0 +1,705 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::atom::Atom;
+use crate::trajectory::Snapshot;
+use crate::vectored::Vectored;
+
+//Implementation of the GROMACS xtc format, following the compression
+//scheme of the reference xdrfile library. All values are big endian
+//XDR, coordinates are in nm and time in ps.
+
+const MAGIC: i32 = 1995;
+
+//coordinates are stored as integers in units of 1/PRECISION nm.
+const PRECISION: f32 = 1000.0;
+
+const FIRSTIDX: usize = 9;
+
+const MAGICINTS: [u32; 73] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 10, 12, 16, 20, 25, 32, 40, 50, 64, 80, 101, 128, 161, 203, 256,
+    322, 406, 512, 645, 812, 1024, 1290, 1625, 2048, 2580, 3250, 4096, 5060, 6501, 8192, 10321,
+    13003, 16384, 20642, 26007, 32768, 41285, 52015, 65536, 82570, 104031, 131072, 165140, 208063,
+    262144, 330280, 416127, 524287, 660561, 832255, 1048576, 1321122, 1664510, 2097152, 2642245,
+    3329021, 4194304, 5284491, 6658042, 8388607, 10568983, 13316085, 16777216,
+];
+
+const LASTIDX: usize = MAGICINTS.len();
+
+///Append a frame to an xtc file. `time` is in fs.
+pub fn write_frame(path: &str, step: usize, time: f64, atoms: &[Atom]) {
+    let coords = atoms
+        .iter()
+        .flat_map(|x| {
+            let pos = x.pos.as_vec();
+            [pos.x, pos.y, pos.z]
+        })
+        //Angstrom to nm
+        .map(|x| (x / 10.0) as f32)
+        .collect::<Vec<f32>>();
+
+    let mut buffer: Vec<u8> = vec![];
+    put_i32(&mut buffer, MAGIC);
+    put_i32(&mut buffer, atoms.len() as i32);
+    put_i32(&mut buffer, step as i32);
+    //fs to ps
+    put_f32(&mut buffer, (time / 1000.0) as f32);
+    //no periodic box
+    for _ in 0..9 {
+        put_f32(&mut buffer, 0.0);
+    }
+    compress(&mut buffer, &coords).expect("coordinates too large for xtc compression");
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .expect("failed to report xtc trajectory");
+    file.write_all(&buffer).expect("failed to write xtc frame");
+}
+
+///Read every frame of an xtc file.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Snapshot>> {
+    let mut buffer: Vec<u8> = vec![];
+    File::open(path.as_ref())
+        .with_context(|| format!("failed to open {}", path.as_ref().display()))?
+        .read_to_end(&mut buffer)?;
+    let mut reader = XdrReader::new(&buffer);
+
+    let mut frames = vec![];
+    while !reader.is_empty() {
+        if reader.i32()? != MAGIC {
+            bail!("bad magic number in xtc frame {}", frames.len());
+        }
+        let num_atoms = reader.i32()? as usize;
+        let step = reader.i32()? as usize;
+        let time = reader.f32()? as f64 * 1000.0;
+        for _ in 0..9 {
+            reader.f32()?;
+        }
+        let coords = decompress(&mut reader, num_atoms)?;
+        frames.push(Snapshot {
+            step,
+            time,
+            //nm to Angstrom
+            coords: coords
+                .chunks(3)
+                .map(|x| [x[0] as f64 * 10.0, x[1] as f64 * 10.0, x[2] as f64 * 10.0])
+                .collect(),
+        });
+    }
+    Ok(frames)
+}
+
+fn put_i32(buffer: &mut Vec<u8>, value: i32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_f32(buffer: &mut Vec<u8>, value: f32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+fn compress(buffer: &mut Vec<u8>, coords: &[f32]) -> Result<()> {
+    let size = coords.len() / 3;
+    put_i32(buffer, size as i32);
+
+    //small systems are stored uncompressed
+    if size <= 9 {
+        coords.iter().for_each(|x| put_f32(buffer, *x));
+        return Ok(());
+    }
+
+    put_f32(buffer, PRECISION);
+
+    let mut ints: Vec<i32> = Vec::with_capacity(coords.len());
+    let mut minint = [i32::MAX; 3];
+    let mut maxint = [i32::MIN; 3];
+    let mut mindiff = i64::MAX;
+    let mut old = [0i32; 3];
+    for (index, atom) in coords.chunks(3).enumerate() {
+        let mut lint = [0i32; 3];
+        for k in 0..3 {
+            let value = atom[k] * PRECISION;
+            let rounded = if value >= 0.0 {
+                value + 0.5
+            } else {
+                value - 0.5
+            };
+            if rounded.abs() > (i32::MAX - 2) as f32 {
+                bail!("coordinate {} out of range", atom[k]);
+            }
+            lint[k] = rounded as i32;
+            minint[k] = minint[k].min(lint[k]);
+            maxint[k] = maxint[k].max(lint[k]);
+        }
+        let diff = (0..3)
+            .map(|k| (old[k] as i64 - lint[k] as i64).abs())
+            .sum::<i64>();
+        if index > 0 && diff < mindiff {
+            mindiff = diff;
+        }
+        old = lint;
+        ints.extend_from_slice(&lint);
+    }
+    minint.iter().for_each(|x| put_i32(buffer, *x));
+    maxint.iter().for_each(|x| put_i32(buffer, *x));
+
+    let mut sizeint = [0u32; 3];
+    for k in 0..3 {
+        let range = maxint[k] as i64 - minint[k] as i64;
+        if range >= (i32::MAX / 2 - 1) as i64 {
+            bail!("coordinate range too large to compress");
+        }
+        sizeint[k] = (range + 1) as u32;
+    }
+    let mut bitsizeint = [0u32; 3];
+    let bitsize = if (sizeint[0] | sizeint[1] | sizeint[2]) > 0xffffff {
+        for k in 0..3 {
+            bitsizeint[k] = sizeofint(sizeint[k]);
+        }
+        0
+    } else {
+        sizeofints(&sizeint)
+    };
+
+    let mut smallidx = FIRSTIDX;
+    while smallidx < LASTIDX - 1 && (MAGICINTS[smallidx] as i64) < mindiff {
+        smallidx += 1;
+    }
+    put_i32(buffer, smallidx as i32);
+
+    let maxidx = (smallidx + 8).min(LASTIDX - 1);
+    let minidx = maxidx - 8;
+    let mut smaller = (MAGICINTS[FIRSTIDX.max(smallidx - 1)] / 2) as i32;
+    let mut smallnum = (MAGICINTS[smallidx] / 2) as i32;
+    let mut sizesmall = [MAGICINTS[smallidx]; 3];
+    let larger = (MAGICINTS[maxidx] / 2) as i32;
+
+    let mut bits = BitWriter::new();
+    let mut prevcoord = [0i32; 3];
+    let mut prevrun: i32 = -1;
+    let mut i = 0;
+    while i < size {
+        let mut is_small = false;
+        let at = |index: usize| index * 3;
+        let mut is_smaller: i32 = if smallidx < maxidx
+            && i >= 1
+            && (0..3).all(|k| (ints[at(i) + k] - prevcoord[k]).abs() < larger)
+        {
+            1
+        } else if smallidx > minidx {
+            -1
+        } else {
+            0
+        };
+        if i + 1 < size && (0..3).all(|k| (ints[at(i) + k] - ints[at(i + 1) + k]).abs() < smallnum)
+        {
+            //interchange first with second atom for better
+            //compression of water molecules
+            for k in 0..3 {
+                ints.swap(at(i) + k, at(i + 1) + k);
+            }
+            is_small = true;
+        }
+
+        let tmpcoord = [0, 1, 2].map(|k| (ints[at(i) + k] - minint[k]) as u32);
+        if bitsize == 0 {
+            for k in 0..3 {
+                bits.send(bitsizeint[k], tmpcoord[k]);
+            }
+        } else {
+            bits.send_ints(bitsize, &sizeint, &tmpcoord);
+        }
+        prevcoord = [0, 1, 2].map(|k| ints[at(i) + k]);
+        i += 1;
+
+        let mut run: usize = 0;
+        let mut tmp = [0u32; 24];
+        if !is_small && is_smaller == -1 {
+            is_smaller = 0;
+        }
+        while is_small && run < 8 * 3 {
+            let this = [0, 1, 2].map(|k| ints[at(i) + k]);
+            if is_smaller == -1
+                && (0..3)
+                    .map(|k| ((this[k] - prevcoord[k]) as i64).pow(2))
+                    .sum::<i64>()
+                    >= (smaller as i64).pow(2)
+            {
+                is_smaller = 0;
+            }
+            for k in 0..3 {
+                tmp[run] = (this[k] - prevcoord[k] + smallnum) as u32;
+                run += 1;
+            }
+            prevcoord = this;
+            i += 1;
+            is_small =
+                i < size && (0..3).all(|k| (ints[at(i) + k] - prevcoord[k]).abs() < smallnum);
+        }
+        if run as i32 != prevrun || is_smaller != 0 {
+            prevrun = run as i32;
+            //flag the change in run length
+            bits.send(1, 1);
+            bits.send(5, (run as i32 + is_smaller + 1) as u32);
+        } else {
+            bits.send(1, 0);
+        }
+        for k in (0..run).step_by(3) {
+            bits.send_ints(smallidx as u32, &sizesmall, &tmp[k..k + 3]);
+        }
+        if is_smaller != 0 {
+            smallidx = (smallidx as i32 + is_smaller) as usize;
+            if is_smaller < 0 {
+                smallnum = smaller;
+                smaller = match smallidx > FIRSTIDX {
+                    true => (MAGICINTS[smallidx - 1] / 2) as i32,
+                    false => 0,
+                };
+            } else {
+                smaller = smallnum;
+                smallnum = (MAGICINTS[smallidx] / 2) as i32;
+            }
+            sizesmall = [MAGICINTS[smallidx]; 3];
+        }
+    }
+
+    let bytes = bits.finish();
+    put_i32(buffer, bytes.len() as i32);
+    buffer.extend_from_slice(&bytes);
+    //XDR opaque data is padded to a multiple of four bytes
+    buffer.resize(buffer.len() + (4 - bytes.len() % 4) % 4, 0);
+    Ok(())
+}
+
+fn decompress(reader: &mut XdrReader, num_atoms: usize) -> Result<Vec<f32>> {
+    let size = reader.i32()? as usize;
+    if size != num_atoms {
+        bail!("xtc frame atom counts do not agree");
+    }
+    if size <= 9 {
+        return (0..size * 3).map(|_| reader.f32()).collect();
+    }
+    let precision = reader.f32()?;
+    let inv_precision = 1.0 / precision;
+
+    let minint = [reader.i32()?, reader.i32()?, reader.i32()?];
+    let maxint = [reader.i32()?, reader.i32()?, reader.i32()?];
+    let sizeint = [0, 1, 2].map(|k| (maxint[k] as i64 - minint[k] as i64 + 1) as u32);
+    let mut bitsizeint = [0u32; 3];
+    let bitsize = if (sizeint[0] | sizeint[1] | sizeint[2]) > 0xffffff {
+        for k in 0..3 {
+            bitsizeint[k] = sizeofint(sizeint[k]);
+        }
+        0
+    } else {
+        sizeofints(&sizeint)
+    };
+
+    let mut smallidx = reader.i32()? as usize;
+    if !(FIRSTIDX..LASTIDX).contains(&smallidx) {
+        bail!("invalid xtc compression index");
+    }
+    let mut smaller = (MAGICINTS[FIRSTIDX.max(smallidx - 1)] / 2) as i32;
+    let mut smallnum = (MAGICINTS[smallidx] / 2) as i32;
+    let mut sizesmall = [MAGICINTS[smallidx]; 3];
+
+    let length = reader.i32()? as usize;
+    let mut bits = BitReader::new(reader.opaque(length)?);
+
+    let mut result: Vec<f32> = Vec::with_capacity(size * 3);
+    let mut run: i32 = 0;
+    let mut i = 0;
+    while i < size {
+        let mut this = [0i32; 3];
+        if bitsize == 0 {
+            for k in 0..3 {
+                this[k] = bits.receive(bitsizeint[k])? as i32;
+            }
+        } else {
+            let value = bits.receive_ints(bitsize, &sizeint)?;
+            this = value.map(|x| x as i32);
+        }
+        i += 1;
+        for k in 0..3 {
+            this[k] += minint[k];
+        }
+        let mut prevcoord = this;
+
+        let mut is_smaller = 0;
+        if bits.receive(1)? == 1 {
+            run = bits.receive(5)? as i32;
+            is_smaller = run % 3;
+            run -= is_smaller;
+            is_smaller -= 1;
+        }
+        if run > 0 {
+            for k in (0..run).step_by(3) {
+                let value = bits.receive_ints(smallidx as u32, &sizesmall)?;
+                i += 1;
+                let mut small = [0, 1, 2].map(|x| value[x] as i32 + prevcoord[x] - smallnum);
+                if k == 0 {
+                    //undo the interchange of the first and second atom
+                    std::mem::swap(&mut small, &mut prevcoord);
+                    result.extend(prevcoord.map(|x| x as f32 * inv_precision));
+                } else {
+                    prevcoord = small;
+                }
+                result.extend(small.map(|x| x as f32 * inv_precision));
+            }
+        } else {
+            result.extend(this.map(|x| x as f32 * inv_precision));
+        }
+
+        smallidx = (smallidx as i32 + is_smaller) as usize;
+        if !(FIRSTIDX..LASTIDX).contains(&smallidx) {
+            bail!("invalid xtc compression index");
+        }
+        if is_smaller < 0 {
+            smallnum = smaller;
+            smaller = match smallidx > FIRSTIDX {
+                true => (MAGICINTS[smallidx - 1] / 2) as i32,
+                false => 0,
+            };
+        } else if is_smaller > 0 {
+            smaller = smallnum;
+            smallnum = (MAGICINTS[smallidx] / 2) as i32;
+        }
+        sizesmall = [MAGICINTS[smallidx]; 3];
+    }
+    if result.len() != size * 3 {
+        bail!("corrupt xtc coordinate block");
+    }
+    Ok(result)
+}
+
+//number of bits needed to store integers up to `size`.
+fn sizeofint(size: u32) -> u32 {
+    let mut num: u64 = 1;
+    let mut bits = 0;
+    while size as u64 >= num && bits < 32 {
+        bits += 1;
+        num <<= 1;
+    }
+    bits
+}
+
+//number of bits needed to store the product of `sizes`.
+fn sizeofints(sizes: &[u32]) -> u32 {
+    let mut bytes: Vec<u32> = vec![1];
+    for size in sizes {
+        let mut tmp: u32 = 0;
+        for byte in bytes.iter_mut() {
+            tmp += *byte * size;
+            *byte = tmp & 0xff;
+            tmp >>= 8;
+        }
+        while tmp != 0 {
+            bytes.push(tmp & 0xff);
+            tmp >>= 8;
+        }
+    }
+    let last = *bytes.last().unwrap();
+    let mut num: u32 = 1;
+    let mut bits = 0;
+    while last >= num {
+        bits += 1;
+        num *= 2;
+    }
+    bits + (bytes.len() as u32 - 1) * 8
+}
+
+//pack three bounded integers into a single number of base `sizes`
+//and return its little endian bytes.
+fn pack_ints(sizes: &[u32], nums: &[u32]) -> Vec<u32> {
+    let mut bytes: Vec<u32> = vec![];
+    let mut tmp = nums[0];
+    loop {
+        bytes.push(tmp & 0xff);
+        tmp >>= 8;
+        if tmp == 0 {
+            break;
+        }
+    }
+    for index in 1..nums.len() {
+        let mut tmp = nums[index];
+        for byte in bytes.iter_mut() {
+            tmp += *byte * sizes[index];
+            *byte = tmp & 0xff;
+            tmp >>= 8;
+        }
+        while tmp != 0 {
+            bytes.push(tmp & 0xff);
+            tmp >>= 8;
+        }
+    }
+    bytes
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    lastbits: u32,
+    lastbyte: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: vec![],
+            lastbits: 0,
+            lastbyte: 0,
+        }
+    }
+
+    fn send(&mut self, mut num_of_bits: u32, num: u32) {
+        while num_of_bits >= 8 {
+            self.lastbyte = (self.lastbyte << 8) | ((num >> (num_of_bits - 8)) & 0xff);
+            self.bytes.push((self.lastbyte >> self.lastbits) as u8);
+            num_of_bits -= 8;
+        }
+        if num_of_bits > 0 {
+            let mask = (1u32 << num_of_bits) - 1;
+            self.lastbyte = (self.lastbyte << num_of_bits) | (num & mask);
+            self.lastbits += num_of_bits;
+            if self.lastbits >= 8 {
+                self.lastbits -= 8;
+                self.bytes.push((self.lastbyte >> self.lastbits) as u8);
+            }
+        }
+    }
+
+    fn send_ints(&mut self, num_of_bits: u32, sizes: &[u32], nums: &[u32]) {
+        let bytes = pack_ints(sizes, nums);
+        let num_of_bytes = bytes.len() as u32;
+        if num_of_bits >= num_of_bytes * 8 {
+            for byte in &bytes {
+                self.send(8, *byte);
+            }
+            self.send(num_of_bits - num_of_bytes * 8, 0);
+        } else {
+            for byte in &bytes[..bytes.len() - 1] {
+                self.send(8, *byte);
+            }
+            self.send(num_of_bits - (num_of_bytes - 1) * 8, bytes[bytes.len() - 1]);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.lastbits > 0 {
+            self.bytes
+                .push((self.lastbyte << (8 - self.lastbits)) as u8);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    count: usize,
+    lastbits: u32,
+    lastbyte: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            bytes,
+            count: 0,
+            lastbits: 0,
+            lastbyte: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<u32> {
+        let byte = match self.bytes.get(self.count) {
+            Some(byte) => *byte as u32,
+            None => bail!("xtc coordinate block ended early"),
+        };
+        self.count += 1;
+        Ok(byte)
+    }
+
+    fn receive(&mut self, mut num_of_bits: u32) -> Result<u32> {
+        let mask = match num_of_bits {
+            32 => u32::MAX,
+            _ => (1u32 << num_of_bits) - 1,
+        };
+        let mut num: u32 = 0;
+        while num_of_bits >= 8 {
+            self.lastbyte = (self.lastbyte << 8) | self.next_byte()?;
+            num |= (self.lastbyte >> self.lastbits) << (num_of_bits - 8);
+            num_of_bits -= 8;
+        }
+        if num_of_bits > 0 {
+            if self.lastbits < num_of_bits {
+                self.lastbits += 8;
+                self.lastbyte = (self.lastbyte << 8) | self.next_byte()?;
+            }
+            self.lastbits -= num_of_bits;
+            num |= (self.lastbyte >> self.lastbits) & ((1u32 << num_of_bits) - 1);
+        }
+        Ok(num & mask)
+    }
+
+    fn receive_ints(&mut self, mut num_of_bits: u32, sizes: &[u32; 3]) -> Result<[u32; 3]> {
+        let mut bytes: Vec<u32> = vec![];
+        while num_of_bits > 8 {
+            bytes.push(self.receive(8)?);
+            num_of_bits -= 8;
+        }
+        if num_of_bits > 0 {
+            bytes.push(self.receive(num_of_bits)?);
+        }
+        let mut nums = [0u32; 3];
+        for index in (1..3).rev() {
+            let mut num: u32 = 0;
+            for byte in bytes.iter_mut().rev() {
+                num = (num << 8) | *byte;
+                let quotient = num / sizes[index];
+                *byte = quotient;
+                num -= quotient * sizes[index];
+            }
+            nums[index] = num;
+        }
+        bytes.resize(4, 0);
+        nums[0] = bytes[0] | (bytes[1] << 8) | (bytes[2] << 16) | (bytes[3] << 24);
+        Ok(nums)
+    }
+}
+
+struct XdrReader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> XdrReader<'a> {
+    fn new(buffer: &'a [u8]) -> XdrReader<'a> {
+        XdrReader { buffer }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.buffer.len() < length {
+            bail!("truncated xtc frame");
+        }
+        let (data, rest) = self.buffer.split_at(length);
+        self.buffer = rest;
+        Ok(data)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn opaque(&mut self, length: usize) -> Result<&'a [u8]> {
+        let data = self.take(length)?;
+        self.take((4 - length % 4) % 4)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::test_atoms;
+    use crate::vectored::Displacement;
+
+    fn round_trip(name: &str, positions: &[[f64; 3]]) {
+        let atoms = test_atoms(&positions.iter().map(|x| ("C", *x)).collect::<Vec<_>>());
+        let path = std::env::temp_dir().join(format!("ezaimd_{}_{}.xtc", name, std::process::id()));
+        let path_str = path.to_str().unwrap();
+        std::fs::write(&path, b"").unwrap();
+        write_frame(path_str, 0, 0.0, &atoms);
+        let mut moved = atoms.clone();
+        for atom in moved.iter_mut() {
+            atom.pos = atom.pos + Displacement::new(0.37, -1.21, 2.05);
+        }
+        write_frame(path_str, 5, 2.5, &moved);
+        let frames = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].step, 5);
+        assert!((frames[1].time - 2.5).abs() < 1e-3);
+        for (frame, atoms) in frames.iter().zip([&atoms, &moved]) {
+            assert_eq!(frame.coords.len(), atoms.len());
+            for (read, atom) in frame.coords.iter().zip(atoms.iter()) {
+                let pos = atom.pos.as_vec();
+                for (a, b) in read.iter().zip([pos.x, pos.y, pos.z]) {
+                    //half of the 0.001 nm precision
+                    assert!((a - b).abs() <= 0.0051, "{} read back as {}", b, a);
+                }
+            }
+        }
+    }
+
+    //reference.xtc holds the atoms of `compressed_frame` in nm, then the
+    //same moved by (0.037, -0.121, 0.205) nm at step 5 and 2.5 fs, in a
+    //5 nm box. It was written by tests/data/reference_xtc.c, the
+    //xdr3dfcoord compression of libxdrfile transcribed to C, not by
+    //`compress`.
+    #[test]
+    fn reference_file() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/reference.xtc");
+        let frames = read(path).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].step, frames[1].step), (0, 5));
+        assert!((frames[1].time - 2.5).abs() < 1e-3);
+        for (frame, shift) in frames.iter().zip([[0.0; 3], [0.037, -0.121, 0.205]]) {
+            let mut expected = vec![];
+            for i in 0..8 {
+                let base = [0.31 * i as f64, -0.17 * i as f64, 0.05 * (i * i) as f64];
+                expected.push(base);
+                expected.push([base[0] + 0.096, base[1], base[2]]);
+                expected.push([base[0] - 0.024, base[1] + 0.093, base[2]]);
+            }
+            expected.push([-25.0, 18.0, 39.99]);
+            expected.push([0.0001, 0.0002, -0.0003]);
+            assert_eq!(frame.coords.len(), expected.len());
+            for (read, position) in frame.coords.iter().zip(&expected) {
+                for k in 0..3 {
+                    let nm = position[k] + shift[k];
+                    //within the 0.001 nm precision
+                    assert!(
+                        (read[k] / 10.0 - nm).abs() <= 0.0005 + 1e-6,
+                        "{} nm read as {} A",
+                        nm,
+                        read[k]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uncompressed_frame() {
+        round_trip(
+            "small",
+            &[[0.0, 0.0, 0.0], [1.1, -0.4, 2.3], [-3.2, 0.9, 0.1]],
+        );
+    }
+
+    #[test]
+    fn compressed_frame() {
+        //water-like triplets close together exercise the run-length
+        //coding, the distant atoms the large integers
+        let mut positions = vec![];
+        for i in 0..8 {
+            let base = [3.1 * i as f64, -1.7 * i as f64, 0.5 * (i * i) as f64];
+            positions.push(base);
+            positions.push([base[0] + 0.96, base[1], base[2]]);
+            positions.push([base[0] - 0.24, base[1] + 0.93, base[2]]);
+        }
+        positions.push([-250.0, 180.0, 399.9]);
+        positions.push([0.001, 0.002, -0.003]);
+        round_trip("large", &positions);
+    }
+}
@@ -0,0 +1,336 @@
+/* Writes reference.xtc with the coordinate compression of xdr3dfcoord
+   from libxdrfile (xdrfile.c) transcribed to C, independently of the
+   Rust encoder: cc reference_xtc.c -o gen && ./gen reference.xtc */
+#include <limits.h>
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static const int magicints[] = {
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 10, 12, 16, 20, 25, 32, 40, 50, 64,
+    80, 101, 128, 161, 203, 256, 322, 406, 512, 645, 812, 1024, 1290,
+    1625, 2048, 2580, 3250, 4096, 5060, 6501, 8192, 10321, 13003,
+    16384, 20642, 26007, 32768, 41285, 52015, 65536, 82570, 104031,
+    131072, 165140, 208063, 262144, 330280, 416127, 524287, 660561,
+    832255, 1048576, 1321122, 1664510, 2097152, 2642245, 3329021,
+    4194304, 5284491, 6658042, 8388607, 10568983, 13316085, 16777216};
+#define FIRSTIDX 9
+#define LASTIDX ((int)(sizeof(magicints) / sizeof(*magicints)))
+
+static FILE *out;
+static void write_int(int v) {
+  unsigned int u = (unsigned int)v;
+  unsigned char b[4] = {u >> 24, u >> 16, u >> 8, u};
+  fwrite(b, 1, 4, out);
+}
+static void write_float(float f) {
+  int v;
+  memcpy(&v, &f, 4);
+  write_int(v);
+}
+
+static int sizeofint(const int size) {
+  unsigned int num = 1;
+  int num_of_bits = 0;
+  while (size >= num && num_of_bits < 32) {
+    num_of_bits++;
+    num <<= 1;
+  }
+  return num_of_bits;
+}
+
+static int sizeofints(const int num_of_ints, unsigned int sizes[]) {
+  int i, num;
+  unsigned int num_of_bytes, num_of_bits, bytes[32], bytecnt, tmp;
+  num_of_bytes = 1;
+  bytes[0] = 1;
+  num_of_bits = 0;
+  for (i = 0; i < num_of_ints; i++) {
+    tmp = 0;
+    for (bytecnt = 0; bytecnt < num_of_bytes; bytecnt++) {
+      tmp = bytes[bytecnt] * sizes[i] + tmp;
+      bytes[bytecnt] = tmp & 0xff;
+      tmp >>= 8;
+    }
+    while (tmp != 0) {
+      bytes[bytecnt++] = tmp & 0xff;
+      tmp >>= 8;
+    }
+    num_of_bytes = bytecnt;
+  }
+  num = 1;
+  num_of_bytes--;
+  while (bytes[num_of_bytes] >= num) {
+    num_of_bits++;
+    num *= 2;
+  }
+  return num_of_bits + num_of_bytes * 8;
+}
+
+static void sendbits(int buf[], int num_of_bits, int num) {
+  unsigned int cnt, lastbyte;
+  int lastbits;
+  unsigned char *cbuf = ((unsigned char *)buf) + 3 * sizeof(*buf);
+  cnt = (unsigned int)buf[0];
+  lastbits = buf[1];
+  lastbyte = (unsigned int)buf[2];
+  while (num_of_bits >= 8) {
+    lastbyte = (lastbyte << 8) | ((num >> (num_of_bits - 8)) /* & 0xff */);
+    cbuf[cnt++] = lastbyte >> lastbits;
+    num_of_bits -= 8;
+  }
+  if (num_of_bits > 0) {
+    lastbyte = (lastbyte << num_of_bits) | num;
+    lastbits += num_of_bits;
+    if (lastbits >= 8) {
+      lastbits -= 8;
+      cbuf[cnt++] = lastbyte >> lastbits;
+    }
+  }
+  buf[0] = cnt;
+  buf[1] = lastbits;
+  buf[2] = lastbyte;
+  if (lastbits > 0) {
+    cbuf[cnt] = lastbyte << (8 - lastbits);
+  }
+}
+
+static void sendints(int buf[], const int num_of_ints, const int num_of_bits,
+                     unsigned int sizes[], unsigned int nums[]) {
+  int i, num_of_bytes, bytecnt;
+  unsigned int bytes[32], tmp;
+  tmp = nums[0];
+  num_of_bytes = 0;
+  do {
+    bytes[num_of_bytes++] = tmp & 0xff;
+    tmp >>= 8;
+  } while (tmp != 0);
+  for (i = 1; i < num_of_ints; i++) {
+    if (nums[i] >= sizes[i]) {
+      fprintf(stderr, "major breakdown in sendints\n");
+      exit(1);
+    }
+    tmp = nums[i];
+    for (bytecnt = 0; bytecnt < num_of_bytes; bytecnt++) {
+      tmp = bytes[bytecnt] * sizes[i] + tmp;
+      bytes[bytecnt] = tmp & 0xff;
+      tmp >>= 8;
+    }
+    while (tmp != 0) {
+      bytes[bytecnt++] = tmp & 0xff;
+      tmp >>= 8;
+    }
+    num_of_bytes = bytecnt;
+  }
+  if (num_of_bits >= num_of_bytes * 8) {
+    for (i = 0; i < num_of_bytes; i++)
+      sendbits(buf, 8, bytes[i]);
+    sendbits(buf, num_of_bits - num_of_bytes * 8, 0);
+  } else {
+    for (i = 0; i < num_of_bytes - 1; i++)
+      sendbits(buf, 8, bytes[i]);
+    sendbits(buf, num_of_bits - (num_of_bytes - 1) * 8, bytes[i]);
+  }
+}
+
+static void compress(float *ptr, int size, float precision) {
+  int minint[3], maxint[3], *lip, diff;
+  int mindiff, lint1, lint2, lint3, oldlint1, oldlint2, oldlint3, smallidx;
+  int minidx, maxidx;
+  unsigned sizeint[3], sizesmall[3], bitsizeint[3], size3;
+  int k, *buf1, lfp_i, prevcoord[3], *thiscoord;
+  unsigned tmpcoord[30];
+  int smallnum, smaller, larger, i, is_small, is_smaller, run, prevrun;
+  int bitsize, tmp;
+  float lf;
+  int *ip;
+
+  write_int(size);
+  size3 = size * 3;
+  if (size <= 9) {
+    for (i = 0; i < (int)size3; i++)
+      write_float(ptr[i]);
+    return;
+  }
+  write_float(precision);
+  ip = malloc(size3 * sizeof(int));
+  buf1 = calloc(size3 * 4 + 100, sizeof(int));
+  buf1[0] = buf1[1] = buf1[2] = 0;
+  minint[0] = minint[1] = minint[2] = INT_MAX;
+  maxint[0] = maxint[1] = maxint[2] = INT_MIN;
+  prevrun = -1;
+  lip = ip;
+  mindiff = INT_MAX;
+  oldlint1 = oldlint2 = oldlint3 = 0;
+  for (lfp_i = 0; lfp_i < (int)size3; lfp_i += 3) {
+    int l[3];
+    for (k = 0; k < 3; k++) {
+      if (ptr[lfp_i + k] >= 0.0)
+        lf = ptr[lfp_i + k] * precision + 0.5;
+      else
+        lf = ptr[lfp_i + k] * precision - 0.5;
+      l[k] = lf;
+      if (l[k] < minint[k]) minint[k] = l[k];
+      if (l[k] > maxint[k]) maxint[k] = l[k];
+      *lip++ = l[k];
+    }
+    lint1 = l[0]; lint2 = l[1]; lint3 = l[2];
+    diff = abs(oldlint1 - lint1) + abs(oldlint2 - lint2) + abs(oldlint3 - lint3);
+    if (diff < mindiff && lfp_i > 0)
+      mindiff = diff;
+    oldlint1 = lint1; oldlint2 = lint2; oldlint3 = lint3;
+  }
+  for (k = 0; k < 3; k++) write_int(minint[k]);
+  for (k = 0; k < 3; k++) write_int(maxint[k]);
+  for (k = 0; k < 3; k++) sizeint[k] = maxint[k] - minint[k] + 1;
+  if ((sizeint[0] | sizeint[1] | sizeint[2]) > 0xffffff) {
+    for (k = 0; k < 3; k++) bitsizeint[k] = sizeofint(sizeint[k]);
+    bitsize = 0;
+  } else {
+    bitsize = sizeofints(3, sizeint);
+  }
+  smallidx = FIRSTIDX;
+  while (smallidx < LASTIDX && magicints[smallidx] < mindiff)
+    smallidx++;
+  write_int(smallidx);
+  tmp = smallidx + 8;
+  maxidx = (LASTIDX < tmp) ? LASTIDX : tmp;
+  minidx = maxidx - 8;
+  tmp = smallidx - 1;
+  tmp = (FIRSTIDX > tmp) ? FIRSTIDX : tmp;
+  smaller = magicints[tmp] / 2;
+  smallnum = magicints[smallidx] / 2;
+  sizesmall[0] = sizesmall[1] = sizesmall[2] = magicints[smallidx];
+  larger = magicints[maxidx] / 2;
+  i = 0;
+  while (i < size) {
+    is_small = 0;
+    thiscoord = ip + i * 3;
+    if (smallidx < maxidx && i >= 1 && abs(thiscoord[0] - prevcoord[0]) < larger &&
+        abs(thiscoord[1] - prevcoord[1]) < larger &&
+        abs(thiscoord[2] - prevcoord[2]) < larger) {
+      is_smaller = 1;
+    } else if (smallidx > minidx) {
+      is_smaller = -1;
+    } else {
+      is_smaller = 0;
+    }
+    if (i + 1 < size) {
+      if (abs(thiscoord[0] - thiscoord[3]) < smallnum &&
+          abs(thiscoord[1] - thiscoord[4]) < smallnum &&
+          abs(thiscoord[2] - thiscoord[5]) < smallnum) {
+        for (k = 0; k < 3; k++) {
+          tmp = thiscoord[k];
+          thiscoord[k] = thiscoord[k + 3];
+          thiscoord[k + 3] = tmp;
+        }
+        is_small = 1;
+      }
+    }
+    for (k = 0; k < 3; k++) tmpcoord[k] = thiscoord[k] - minint[k];
+    if (bitsize == 0) {
+      for (k = 0; k < 3; k++) sendbits(buf1, bitsizeint[k], tmpcoord[k]);
+    } else {
+      sendints(buf1, 3, bitsize, sizeint, tmpcoord);
+    }
+    for (k = 0; k < 3; k++) prevcoord[k] = thiscoord[k];
+    thiscoord = thiscoord + 3;
+    i++;
+    run = 0;
+    if (is_small == 0 && is_smaller == -1)
+      is_smaller = 0;
+    while (is_small && run < 8 * 3) {
+      if (is_smaller == -1 &&
+          ((thiscoord[0] - prevcoord[0]) * (thiscoord[0] - prevcoord[0]) +
+           (thiscoord[1] - prevcoord[1]) * (thiscoord[1] - prevcoord[1]) +
+           (thiscoord[2] - prevcoord[2]) * (thiscoord[2] - prevcoord[2])) >=
+              smaller * smaller) {
+        is_smaller = 0;
+      }
+      for (k = 0; k < 3; k++) tmpcoord[run++] = thiscoord[k] - prevcoord[k] + smallnum;
+      for (k = 0; k < 3; k++) prevcoord[k] = thiscoord[k];
+      i++;
+      thiscoord = thiscoord + 3;
+      is_small = 0;
+      if (i < size && abs(thiscoord[0] - prevcoord[0]) < smallnum &&
+          abs(thiscoord[1] - prevcoord[1]) < smallnum &&
+          abs(thiscoord[2] - prevcoord[2]) < smallnum) {
+        is_small = 1;
+      }
+    }
+    if (run != prevrun || is_smaller != 0) {
+      prevrun = run;
+      sendbits(buf1, 1, 1);
+      sendbits(buf1, 5, run + is_smaller + 1);
+    } else {
+      sendbits(buf1, 1, 0);
+    }
+    for (k = 0; k < run; k += 3)
+      sendints(buf1, 3, smallidx, sizesmall, &tmpcoord[k]);
+    if (is_smaller != 0) {
+      smallidx += is_smaller;
+      if (is_smaller < 0) {
+        smallnum = smaller;
+        if (smallidx > FIRSTIDX)
+          smaller = magicints[smallidx - 1] / 2;
+        else
+          smaller = 0;
+      } else if (is_smaller > 0) {
+        smaller = smallnum;
+        smallnum = magicints[smallidx] / 2;
+      }
+      sizesmall[0] = sizesmall[1] = sizesmall[2] = magicints[smallidx];
+    }
+  }
+  if (buf1[1] != 0)
+    buf1[0]++;
+  write_int(buf1[0]);
+  fwrite((unsigned char *)(buf1 + 3), 1, buf1[0], out);
+  {
+    static const unsigned char zero[4] = {0};
+    fwrite(zero, 1, (4 - buf1[0] % 4) % 4, out);
+  }
+  free(ip);
+  free(buf1);
+}
+
+static void frame(int natoms, int step, float time, float *x) {
+  write_int(1995);
+  write_int(natoms);
+  write_int(step);
+  write_float(time);
+  for (int a = 0; a < 3; a++)
+    for (int b = 0; b < 3; b++)
+      write_float(a == b ? 5.0f : 0.0f);
+  compress(x, natoms, 1000.0f);
+}
+
+/* positions in nm: eight water-like triplets and two distant atoms */
+static int positions(double shift[3], float *x) {
+  int n = 0;
+  for (int i = 0; i < 8; i++) {
+    double base[3] = {0.31 * i, -0.17 * i, 0.05 * i * i};
+    double offsets[3][3] = {{0, 0, 0}, {0.096, 0, 0}, {-0.024, 0.093, 0}};
+    for (int j = 0; j < 3; j++, n++)
+      for (int k = 0; k < 3; k++)
+        x[3 * n + k] = (float)(base[k] + offsets[j][k] + shift[k]);
+  }
+  double extra[2][3] = {{-25.0, 18.0, 39.99}, {0.0001, 0.0002, -0.0003}};
+  for (int j = 0; j < 2; j++, n++)
+    for (int k = 0; k < 3; k++)
+      x[3 * n + k] = (float)(extra[j][k] + shift[k]);
+  return n;
+}
+
+int main(int argc, char **argv) {
+  float x[26 * 3];
+  double none[3] = {0, 0, 0}, moved[3] = {0.037, -0.121, 0.205};
+  out = fopen(argv[1], "wb");
+  int n = positions(none, x);
+  frame(n, 0, 0.0f, x);
+  positions(moved, x);
+  frame(n, 5, 0.0025f, x);
+  fclose(out);
+  return 0;
+}